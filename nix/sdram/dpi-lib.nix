@@ -1,7 +1,7 @@
 # SPDX-License-Identifier: Apache-2.0
 # SPDX-FileCopyrightText: 2024 Jiuyang Liu <liu@jiuyang.me>
 
-{ lib, rustPlatform, tbConfig, sv2023 ? true, vpi ? false, enable-trace ? false
+{ lib, rustPlatform, sv2023 ? true, vpi ? false, enable-trace ? false
 , timescale ? 1 }:
 
 rustPlatform.buildRustPackage rec {
@@ -11,10 +11,7 @@ rustPlatform.buildRustPackage rec {
   buildFeatures = lib.optionals sv2023 [ "sv2023" ]
     ++ lib.optionals vpi [ "vpi" ] ++ lib.optionals enable-trace [ "trace" ];
 
-  env = {
-    TIMEOUT = tbConfig.timeout;
-    CLOCK_FLIP_TIME = tbConfig.testVerbatimParameter.clockFlipTick * timescale;
-  };
+  env = { TIMESCALE = timescale; };
 
   passthru = {
     inherit enable-trace;
@@ -133,7 +133,7 @@ class AXI4MasterAgent(parameter: AXI4MasterAgentParameter)
             parameter.axiParameter.awUserWidth,
             parameter.axiParameter.wUserWidth
           )
-        )(when.cond && !io.gateWrite)
+        )(when.cond && !io.gateWrite, io.channelId)
         awFifo(awWPtr).index := 0.U
         when(awFifo(awWPtr).payload.dataValid) {
           awFifo(awWPtr).addrValid := true.B
@@ -236,7 +236,8 @@ class AXI4MasterAgent(parameter: AXI4MasterAgentParameter)
             parameter.axiParameter.arUserWidth
           )
         )(
-          when.cond && !io.gateRead
+          when.cond && !io.gateRead,
+          io.channelId
         )
         when(arFifo(arWPtr).payload.valid) {
           arFifo(arWPtr).payload.valid := false.B
@@ -271,7 +272,9 @@ class AXI4MasterAgent(parameter: AXI4MasterAgentParameter)
         )(
           io.clock,
           when.cond && !io.gateRead,
-          channel.RDATA,
+          // 8, 16, 32 and 64 bits would be passed by value as a C integer,
+          // one more bit keeps RDATA an svBitVecVal array at any bus width
+          channel.RDATA.pad(parameter.axiParameter.dataWidth + 1),
           channel.RID.asTypeOf(UInt(8.W)),
           channel.RLAST.asTypeOf(UInt(8.W)),
           channel.RRESP.asTypeOf(UInt(8.W)),
@@ -23,7 +23,7 @@ case class SDRAMControllerTestBenchParameter(
   timeout:                  Int)
     extends SerializableModuleParameter
 
-class W9825G6KHInterface extends Bundle {
+class SDRAMModelInterface extends Bundle {
   val Dq_i = Input(UInt(32.W))
   val Dq_o = Output(UInt(32.W))
   val Addr = Input(UInt(13.W))
@@ -37,8 +37,36 @@ class W9825G6KHInterface extends Bundle {
   val Dqm = Input(UInt(4.W))
 }
 
-@public
-class W9825G6KH extends FixedIOExtModule(new W9825G6KHInterface)
+object SDRAMModelParameter {
+  implicit def rwP: upickle.default.ReadWriter[SDRAMModelParameter] =
+    upickle.default.macroRW
+}
+
+case class SDRAMModelParameter(chipSelect: Int) extends SerializableModuleParameter
+
+/** The SDRAM chip of one chip select, modelled in sdramemu: `sdram_clock` registers the pins on every rising edge of
+  * `Clk` and returns the value driven on DQ until the next one.
+  */
+@instantiable
+class SDRAMModel(val parameter: SDRAMModelParameter)
+    extends FixedIORawModule(new SDRAMModelInterface)
+    with SerializableModule[SDRAMModelParameter] {
+  io.Dq_o := RawClockedNonVoidFunctionCall("sdram_clock", UInt(32.W))(
+    io.Clk,
+    true.B,
+    parameter.chipSelect.U(8.W),
+    io.Cke.asTypeOf(UInt(8.W)),
+    io.Cs_n.asTypeOf(UInt(8.W)),
+    io.Ras_n.asTypeOf(UInt(8.W)),
+    io.Cas_n.asTypeOf(UInt(8.W)),
+    io.We_n.asTypeOf(UInt(8.W)),
+    // 13 bits would become an svBitVecVal array, every pin is a C integer
+    io.Addr.pad(16),
+    io.Bs.asTypeOf(UInt(8.W)),
+    io.Dqm.asTypeOf(UInt(8.W)),
+    io.Dq_i
+  )
+}
 
 class SDRAMControllerTestBench(val parameter: SDRAMControllerTestBenchParameter)
     extends RawModule
@@ -58,7 +86,9 @@ class SDRAMControllerTestBench(val parameter: SDRAMControllerTestBenchParameter)
       )
     )
   ).tap(_.suggestName(s"axi4_channel_probe"))
-  val w9825g6kb = Instantiate(new W9825G6KH)
+  val sdramModels = Seq.tabulate(parameter.sdramControllerParameter.sdramParameter.csWidth) { index =>
+    Instantiate(new SDRAMModel(SDRAMModelParameter(index))).tap(_.suggestName(s"sdram_model_$index"))
+  }
 
   val initFlag = RegInit(false.B)
   dut.io := DontCare
@@ -85,10 +115,8 @@ class SDRAMControllerTestBench(val parameter: SDRAMControllerTestBenchParameter)
   }
 
   /** SDRAM <-> DUT */
-  Seq
-    .fill(parameter.sdramControllerParameter.sdramParameter.csWidth) {
-      w9825g6kb.io
-    }
+  sdramModels
+    .map(_.io)
     .zipWithIndex
     .foreach { case (bundle, index) =>
       bundle.Addr := dut.io.sdram.a
@@ -97,12 +125,13 @@ class SDRAMControllerTestBench(val parameter: SDRAMControllerTestBenchParameter)
       bundle.Clk := dut.io.sdram.ck(index)
       bundle.Cs_n := dut.io.sdram.cs(index).asBool
       bundle.Dq_i := dut.io.sdram.dqo
-      dut.io.sdram.dqi := bundle.Dq_o
       bundle.Dqm := dut.io.sdram.dqm
       bundle.Ras_n := dut.io.sdram.ras
       bundle.We_n := dut.io.sdram.we
       bundle.Cas_n := dut.io.sdram.cas
     }
+  // a chip select that is not reading drives zero
+  dut.io.sdram.dqi := sdramModels.map(_.io.Dq_o).reduce(_ | _)
 
   override protected def implicitClock: Clock = verbatim.io.clock
 
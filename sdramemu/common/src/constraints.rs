@@ -0,0 +1,152 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use crate::memory_map::MemoryMap;
+
+/// Knobs of the constrained-random traffic, every field may be omitted.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct Constraints {
+  pub burst_weights: BurstWeights,
+  /// Beats per burst, clamped to what the agent can carry
+  pub beats: InclusiveRange,
+  /// log2 of the bytes per beat, clamped to the bus width
+  pub size: InclusiveRange,
+  /// Address windows the bursts are placed in, the whole address space when empty
+  pub address_windows: Vec<AddressWindow>,
+  /// Probability of each byte lane of a beat being strobed
+  pub strobe_density: f64,
+  /// Probability of a FIXED or INCR burst starting at an unaligned address
+  pub unaligned: f64,
+  pub read_write_ratio: ReadWriteRatio,
+  /// Transactions in flight before the generator stops issuing
+  pub max_outstanding: Option<usize>,
+  /// Probability of placing a burst outside the memory map on purpose
+  pub out_of_range: f64,
+  /// Address space outside the memory map, see [`Constraints::apply_memory_map`]
+  #[serde(skip)]
+  pub out_of_range_windows: Vec<AddressWindow>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct BurstWeights {
+  pub fixed: u32,
+  pub incr: u32,
+  pub wrap: u32,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct InclusiveRange {
+  pub min: u8,
+  pub max: u8,
+}
+
+/// `start..end`, in bytes.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct AddressWindow {
+  pub start: u64,
+  pub end: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ReadWriteRatio {
+  pub read: u32,
+  pub write: u32,
+}
+
+impl Default for Constraints {
+  fn default() -> Self {
+    Constraints {
+      burst_weights: BurstWeights { fixed: 1, incr: 1, wrap: 1 },
+      beats: InclusiveRange { min: 1, max: 16 },
+      size: InclusiveRange { min: 0, max: 7 },
+      address_windows: Vec::new(),
+      strobe_density: 1.0,
+      unaligned: 0.0,
+      read_write_ratio: ReadWriteRatio { read: 1, write: 1 },
+      max_outstanding: None,
+      out_of_range: 0.0,
+      out_of_range_windows: Vec::new(),
+    }
+  }
+}
+
+impl Constraints {
+  pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path)
+      .with_context(|| format!("failed to read constraints {}", path.display()))?;
+    let constraints: Constraints = serde_json::from_str(&content)
+      .with_context(|| format!("failed to parse constraints {}", path.display()))?;
+    constraints
+      .validate()
+      .with_context(|| format!("invalid constraints {}", path.display()))?;
+    Ok(constraints)
+  }
+
+  /// Keep bursts within the chips unless address windows were given, and
+  /// take the out-of-range addresses from the holes of `map`.
+  pub fn apply_memory_map(&mut self, map: &MemoryMap, addr_space: u64) {
+    if self.address_windows.is_empty() {
+      self.address_windows = map.windows();
+    }
+    self.out_of_range_windows = map.holes(addr_space);
+  }
+
+  fn validate(&self) -> Result<()> {
+    let weights = &self.burst_weights;
+    if weights.fixed + weights.incr + weights.wrap == 0 {
+      bail!("burstWeights are all zero");
+    }
+    if self.beats.min == 0 || self.beats.min > self.beats.max {
+      bail!("beats must be a non-empty range starting at 1 or above");
+    }
+    if self.size.min > self.size.max {
+      bail!("size is an empty range");
+    }
+    if let Some(window) = self.address_windows.iter().find(|window| window.start >= window.end) {
+      bail!("address window {:#x}..{:#x} is empty", window.start, window.end);
+    }
+    if !(0.0..=1.0).contains(&self.strobe_density) {
+      bail!("strobeDensity must be within 0..=1");
+    }
+    if self.read_write_ratio.read + self.read_write_ratio.write == 0 {
+      bail!("readWriteRatio is all zero");
+    }
+    if !(0.0..=1.0).contains(&self.unaligned) {
+      bail!("unaligned must be within 0..=1");
+    }
+    if !(0.0..=1.0).contains(&self.out_of_range) {
+      bail!("outOfRange must be within 0..=1");
+    }
+    if self.max_outstanding == Some(0) {
+      bail!("maxOutstanding must be at least 1");
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn load_example_constraints() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../configs/NarrowTransferConstraints.json");
+    let constraints = Constraints::load(path).unwrap();
+    assert_eq!(constraints.burst_weights.wrap, 0);
+    assert_eq!(constraints.size.max, 1);
+    assert_eq!(constraints.address_windows.len(), 1);
+    // omitted fields keep their defaults
+    assert_eq!(constraints.max_outstanding, None);
+
+    let invalid: Constraints = serde_json::from_str(r#"{"beats": {"min": 4, "max": 2}}"#).unwrap();
+    assert!(invalid.validate().is_err());
+  }
+}
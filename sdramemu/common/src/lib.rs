@@ -1,9 +1,13 @@
 use anyhow::Result;
 use clap::Parser;
+use std::path::PathBuf;
 use tracing::Level;
 use tracing_subscriber::{EnvFilter, FmtSubscriber};
 
+pub mod constraints;
+pub mod memory_map;
 pub mod rtl_config;
+pub mod sdram_part;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -13,13 +17,9 @@ pub struct CommonArgs {
   #[arg(long, default_value = "info")]
   pub log_level: String,
 
-  /// vlen config
-  #[arg(long, default_value = "32")]
-  pub vlen: u32,
-
-  /// dlen config
-  #[arg(long, default_value = "32")]
-  pub dlen: u32,
+  /// Path to the testbench config json, e.g. configs/SDRAMControllerTestBenchMain.json
+  #[arg(long)]
+  pub config: PathBuf,
 
 }
 
@@ -0,0 +1,106 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use crate::constraints::AddressWindow;
+
+/// Where the SDRAM chips sit in the AXI address space.
+///
+/// The defaults describe the testbench: one W9825G6KH of 32 MiB, of which
+/// the controller decodes `SDRAM_ADDR_W = 24` word address bits, so every
+/// address above the chip aliases onto it.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct MemoryMap {
+  pub base: u64,
+  /// Bytes behind each chip select, a power of two
+  pub chip_select_size: u64,
+  pub chip_selects: u32,
+  /// Out-of-range addresses fold onto the map when set, and are answered
+  /// with SLVERR or DECERR otherwise
+  pub alias: bool,
+}
+
+impl Default for MemoryMap {
+  fn default() -> Self {
+    MemoryMap {
+      base: 0,
+      chip_select_size: 32 << 20,
+      chip_selects: 1,
+      alias: true,
+    }
+  }
+}
+
+impl MemoryMap {
+  pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path)
+      .with_context(|| format!("failed to read memory map {}", path.display()))?;
+    let map: MemoryMap = serde_json::from_str(&content)
+      .with_context(|| format!("failed to parse memory map {}", path.display()))?;
+    if !map.chip_select_size.is_power_of_two() || map.chip_selects == 0 {
+      bail!("invalid memory map {}: chipSelectSize must be a power of two and chipSelects non-zero", path.display());
+    }
+    Ok(map)
+  }
+
+  pub fn size(&self) -> u64 {
+    self.chip_select_size * self.chip_selects as u64
+  }
+
+  /// One window per chip select.
+  pub fn windows(&self) -> Vec<AddressWindow> {
+    (0..self.chip_selects as u64)
+      .map(|cs| AddressWindow {
+        start: self.base + cs * self.chip_select_size,
+        end: self.base + (cs + 1) * self.chip_select_size,
+      })
+      .collect()
+  }
+
+  /// Parts of `0..addr_space` outside the map.
+  pub fn holes(&self, addr_space: u64) -> Vec<AddressWindow> {
+    let end = (self.base + self.size()).min(addr_space);
+    [
+      AddressWindow { start: 0, end: self.base.min(addr_space) },
+      AddressWindow { start: end, end: addr_space },
+    ]
+    .into_iter()
+    .filter(|window| window.start < window.end)
+    .collect()
+  }
+
+  /// The address that is accessed in the chips, `None` when out of range and
+  /// not aliased.
+  pub fn translate(&self, addr: u64) -> Option<u64> {
+    let offset = addr.wrapping_sub(self.base);
+    if offset < self.size() {
+      return Some(addr);
+    }
+    // upper address bits are not decoded
+    self
+      .alias
+      .then(|| self.base + (offset & (self.size().next_power_of_two() - 1)) % self.size())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn translate_and_alias() {
+    let map = MemoryMap { base: 0x1000_0000, chip_selects: 2, ..MemoryMap::default() };
+    assert_eq!(map.windows().len(), 2);
+    assert_eq!(map.windows()[1].start, 0x1200_0000);
+    assert_eq!(map.translate(0x1234_5678), Some(0x1234_5678));
+    assert_eq!(map.translate(0x1400_0004), Some(0x1000_0004));
+    assert_eq!(map.translate(0x0000_0010), Some(0x1000_0010));
+    assert_eq!(map.holes(1 << 32).len(), 2);
+
+    let strict = MemoryMap { alias: false, ..map };
+    assert_eq!(strict.translate(0x1400_0000), None);
+  }
+}
@@ -1,20 +1,101 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// Mirrors `SDRAMControllerTestBenchParameter` as dumped by the elaborator.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct RTLConfig {
-  pub vlen: u32,
-  pub dlen: u32,
+  pub sdram_controller_parameter: SDRAMControllerParameter,
+  pub test_verbatim_parameter: TestVerbatimParameter,
+  pub timeout: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SDRAMControllerParameter {
+  pub axi_parameter: AXIParameter,
+  pub sdram_parameter: SDRAMParameter,
 }
 
-// TODO: read from json
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AXIParameter {
+  pub id_width: u32,
+  pub data_width: u32,
+  pub addr_width: u32,
+  pub user_req_width: u32,
+  pub user_data_width: u32,
+  pub user_resp_width: u32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SDRAMParameter {
+  pub data_width: u32,
+  pub cs_width: u32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TestVerbatimParameter {
+  pub use_async_reset: bool,
+  pub init_function_name: String,
+  pub dump_function_name: String,
+  pub clock_flip_tick: u64,
+  pub reset_flip_tick: u64,
+}
 
 impl RTLConfig {
-  pub fn xlen(&self) -> u32 {
-    32 // TODO: configurable
+  pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path)
+      .with_context(|| format!("failed to read config {}", path.display()))?;
+    serde_json::from_str(&content)
+      .with_context(|| format!("failed to parse config {}", path.display()))
+  }
+
+  pub fn axi(&self) -> &AXIParameter {
+    &self.sdram_controller_parameter.axi_parameter
   }
 
-  pub fn vlen_in_bytes(&self) -> u32 {
-    self.vlen / 8
+  pub fn sdram(&self) -> &SDRAMParameter {
+    &self.sdram_controller_parameter.sdram_parameter
   }
+}
+
+// user widths follow `org.chipsalliance.amba.axi4.bundle.AXI4BundleParameter`
+impl AXIParameter {
+  pub fn aw_user_width(&self) -> u32 {
+    self.user_req_width
+  }
+
+  pub fn w_user_width(&self) -> u32 {
+    self.user_data_width
+  }
+
+  pub fn ar_user_width(&self) -> u32 {
+    self.user_req_width
+  }
+
+  pub fn data_width_in_bytes(&self) -> u32 {
+    self.data_width / 8
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
 
-  pub fn lane_num(&self) -> u32 {
-    self.dlen / self.xlen()
+  #[test]
+  fn load_testbench_config() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../configs/SDRAMControllerTestBenchMain.json");
+    let config = RTLConfig::load(path).unwrap();
+    assert_eq!(config.axi().data_width, 32);
+    assert_eq!(config.axi().id_width, 4);
+    assert_eq!(config.sdram().cs_width, 4);
+    assert_eq!(config.test_verbatim_parameter.clock_flip_tick, 1);
+    assert_eq!(config.timeout, 10000);
   }
 }
@@ -0,0 +1,213 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+/// The SDRAM chip behind every chip select, as seen on its pins.
+///
+/// The defaults describe the W9825G6KH of the testbench: 4 banks of 8192
+/// rows by 512 columns of 16 bits, with the timing of its -6 speed grade.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct SdramPart {
+  pub name: String,
+  pub banks: u32,
+  pub rows: u32,
+  pub columns: u32,
+  /// DQ width in bits, one DQM bit per byte
+  pub data_width: u32,
+  pub timing: SdramTiming,
+}
+
+/// A minimum delay of the datasheet, the larger of `ns` and `clocks`.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimingParameter {
+  pub ns: f64,
+  pub clocks: u32,
+}
+
+impl TimingParameter {
+  const fn ns(ns: f64) -> Self {
+    TimingParameter { ns, clocks: 0 }
+  }
+
+  const fn clocks(clocks: u32) -> Self {
+    TimingParameter { ns: 0.0, clocks }
+  }
+
+  /// Clock cycles at `clock_mhz` covering the delay.
+  pub fn cycles(&self, clock_mhz: f64) -> u64 {
+    // tolerate rounding of the clock period
+    let ns_cycles = (self.ns * clock_mhz / 1000.0 - 1e-9).ceil().max(0.0) as u64;
+    ns_cycles.max(self.clocks as u64)
+  }
+}
+
+/// A CAS latency the part supports, down to a clock period of `min_clock_ns`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct CasLatency {
+  pub cas_latency: u32,
+  pub min_clock_ns: f64,
+}
+
+/// The JEDEC timing table of the part.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct SdramTiming {
+  /// SDRAM clock the controller runs the part at, `SDRAM_MHZ`
+  pub clock_mhz: f64,
+  /// ACTIVE to READ or WRITE
+  pub t_rcd: TimingParameter,
+  /// PRECHARGE to ACTIVE, REFRESH or LOAD MODE REGISTER
+  pub t_rp: TimingParameter,
+  /// ACTIVE to PRECHARGE
+  pub t_ras: TimingParameter,
+  /// ACTIVE to ACTIVE of the same bank
+  pub t_rc: TimingParameter,
+  /// ACTIVE to ACTIVE of another bank
+  pub t_rrd: TimingParameter,
+  /// last write data to PRECHARGE
+  pub t_wr: TimingParameter,
+  /// REFRESH to any command but NOP
+  pub t_rfc: TimingParameter,
+  /// LOAD MODE REGISTER to any command but NOP
+  pub t_mrd: TimingParameter,
+  /// every row is refreshed within it
+  pub t_ref: TimingParameter,
+  /// AUTO REFRESH commands refreshing every row once, `SDRAM_REFRESH_CNT`
+  pub refresh_commands: u32,
+  pub cas_latencies: Vec<CasLatency>,
+}
+
+impl Default for SdramTiming {
+  fn default() -> Self {
+    SdramTiming {
+      clock_mhz: 50.0,
+      t_rcd: TimingParameter::ns(15.0),
+      t_rp: TimingParameter::ns(15.0),
+      t_ras: TimingParameter::ns(42.0),
+      t_rc: TimingParameter::ns(60.0),
+      t_rrd: TimingParameter::clocks(2),
+      t_wr: TimingParameter::clocks(2),
+      t_rfc: TimingParameter::ns(60.0),
+      t_mrd: TimingParameter::clocks(2),
+      t_ref: TimingParameter::ns(64_000_000.0),
+      refresh_commands: 8192,
+      cas_latencies: vec![
+        CasLatency { cas_latency: 2, min_clock_ns: 7.5 },
+        CasLatency { cas_latency: 3, min_clock_ns: 6.0 },
+      ],
+    }
+  }
+}
+
+impl SdramTiming {
+  pub fn clock_ns(&self) -> f64 {
+    1000.0 / self.clock_mhz
+  }
+
+  /// Whether the part runs with `cas_latency` at the clock of the controller.
+  pub fn supports_cas_latency(&self, cas_latency: u32) -> bool {
+    self
+      .cas_latencies
+      .iter()
+      .any(|grade| grade.cas_latency == cas_latency && self.clock_ns() >= grade.min_clock_ns)
+  }
+}
+
+impl Default for SdramPart {
+  fn default() -> Self {
+    SdramPart {
+      name: "W9825G6KH".to_owned(),
+      banks: 4,
+      rows: 8192,
+      columns: 512,
+      data_width: 16,
+      timing: SdramTiming::default(),
+    }
+  }
+}
+
+impl SdramPart {
+  pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path)
+      .with_context(|| format!("failed to read SDRAM part {}", path.display()))?;
+    let part: SdramPart = serde_json::from_str(&content)
+      .with_context(|| format!("failed to parse SDRAM part {}", path.display()))?;
+    part
+      .validate()
+      .with_context(|| format!("invalid SDRAM part {}", path.display()))?;
+    Ok(part)
+  }
+
+  pub fn validate(&self) -> Result<()> {
+    for (name, value) in [("banks", self.banks), ("rows", self.rows), ("columns", self.columns)] {
+      if !value.is_power_of_two() {
+        bail!("{name} must be a power of two, got {value}");
+      }
+    }
+    // A10 is the auto precharge bit of READ and WRITE
+    if self.columns > 1 << 10 {
+      bail!("at most 1024 columns are addressable, got {}", self.columns);
+    }
+    if !matches!(self.data_width, 8 | 16 | 32) {
+      bail!("dataWidth must be 8, 16 or 32, got {}", self.data_width);
+    }
+    if self.timing.clock_mhz <= 0.0 {
+      bail!("timing.clockMhz must be positive, got {}", self.timing.clock_mhz);
+    }
+    let refresh_commands = self.timing.refresh_commands;
+    if refresh_commands == 0 || !self.rows.is_multiple_of(refresh_commands) {
+      bail!("rows must be a multiple of timing.refreshCommands, got {refresh_commands}");
+    }
+    Ok(())
+  }
+
+  pub fn bank_bits(&self) -> u32 {
+    self.banks.ilog2()
+  }
+
+  pub fn column_bits(&self) -> u32 {
+    self.columns.ilog2()
+  }
+
+  /// Rows refreshed by one AUTO REFRESH.
+  pub fn rows_per_refresh(&self) -> u32 {
+    self.rows / self.timing.refresh_commands
+  }
+
+  /// Bytes stored by the whole chip.
+  pub fn size(&self) -> u64 {
+    self.banks as u64 * self.rows as u64 * self.columns as u64 * (self.data_width / 8) as u64
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn default_part_is_w9825g6kh() {
+    let part = SdramPart::default();
+    part.validate().unwrap();
+    assert_eq!(part.size(), 32 << 20);
+    assert_eq!((part.bank_bits(), part.column_bits()), (2, 9));
+
+    let narrow = SdramPart { columns: 2048, ..SdramPart::default() };
+    assert!(narrow.validate().is_err());
+
+    // 20 ns cycles at the 50 MHz of the controller
+    let timing = &part.timing;
+    assert_eq!(timing.t_rcd.cycles(timing.clock_mhz), 1);
+    assert_eq!(timing.t_ras.cycles(timing.clock_mhz), 3);
+    assert_eq!(timing.t_rfc.cycles(timing.clock_mhz), 3);
+    assert_eq!(timing.t_mrd.cycles(timing.clock_mhz), 2);
+    // the 390 cycles between the refreshes of the controller
+    assert_eq!(timing.t_ref.cycles(timing.clock_mhz) / timing.refresh_commands as u64, 390);
+    assert!(timing.supports_cas_latency(2));
+    assert!(!timing.supports_cas_latency(1));
+  }
+}
@@ -0,0 +1,163 @@
+//! Shrinks a failing trace recorded with `--record` to a smaller one that
+//! still fails the same check, by replaying subsets of its transactions
+//! (delta debugging).
+//!
+//! ```text
+//! sdram-shrink --trace fail.trace --output min.trace -- \
+//!     ./result/bin/sdram-vcs-simulator --config configs/SDRAMControllerTestBenchMain.json
+//! ```
+
+use clap::Parser;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(Parser)]
+struct ShrinkArgs {
+    /// Failing trace written by `--record`
+    #[arg(long)]
+    trace: PathBuf,
+
+    /// Where to write the shrunk trace
+    #[arg(long)]
+    output: PathBuf,
+
+    /// Text in the simulator output identifying the failure, taken from the
+    /// first data mismatch of the full trace when omitted
+    #[arg(long)]
+    signature: Option<String>,
+
+    /// Simulator command, `--replay <trace>` is appended to it
+    #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+    simulator: Vec<String>,
+}
+
+/// Runs the simulator on one candidate trace and returns its output.
+fn replay(simulator: &[String], trace: &Path) -> String {
+    let output = Command::new(&simulator[0])
+        .args(&simulator[1..])
+        .arg("--replay")
+        .arg(trace)
+        .output()
+        .unwrap_or_else(|e| panic!("failed to run {}: {e}", simulator[0]));
+    String::from_utf8_lossy(&output.stdout).into_owned() + &String::from_utf8_lossy(&output.stderr)
+}
+
+/// The id and address of the first read data mismatch, which stay the same
+/// as long as the failing read is kept in the trace.
+fn mismatch_signature(output: &str) -> Option<String> {
+    output.lines().find_map(|line| {
+        let end = line.find(" data mismatch")?;
+        let start = line.find("R: id ")?;
+        Some(line[start..end + " data mismatch".len()].to_owned())
+    })
+}
+
+/// Smallest subsequence of `items` for which `fails` still holds, as found by
+/// Zeller's ddmin: try ever finer chunks and their complements.
+fn ddmin<T: Clone>(mut items: Vec<T>, mut fails: impl FnMut(&[T]) -> bool) -> Vec<T> {
+    let mut granularity = 2;
+    while items.len() >= 2 {
+        let chunk = items.len().div_ceil(granularity);
+        let chunks: Vec<&[T]> = items.chunks(chunk).collect();
+        let reduced = chunks
+            .iter()
+            .find(|subset| fails(subset))
+            .map(|subset| (subset.to_vec(), 2))
+            .or_else(|| {
+                (0..chunks.len()).find_map(|skip| {
+                    let complement: Vec<T> = chunks
+                        .iter()
+                        .enumerate()
+                        .filter(|(index, _)| *index != skip)
+                        .flat_map(|(_, subset)| subset.iter().cloned())
+                        .collect();
+                    fails(&complement).then_some((complement, (granularity - 1).max(2)))
+                })
+            });
+        match reduced {
+            Some((subset, next)) => {
+                items = subset;
+                granularity = next;
+            }
+            None if granularity >= items.len() => break,
+            None => granularity = (granularity * 2).min(items.len()),
+        }
+    }
+    items
+}
+
+fn main() {
+    let args = ShrinkArgs::parse();
+    let trace = std::fs::read_to_string(&args.trace)
+        .unwrap_or_else(|e| panic!("failed to read trace {}: {e}", args.trace.display()));
+    // comments such as the seed header are kept, only transactions are dropped
+    let (header, transactions): (Vec<&str>, Vec<&str>) = trace
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .partition(|line| line.trim_start().starts_with('#'));
+
+    let candidate = args.output.with_extension("candidate");
+    let write = |path: &Path, transactions: &[&str]| {
+        let content: String = header
+            .iter()
+            .chain(transactions)
+            .map(|line| format!("{line}\n"))
+            .collect();
+        std::fs::write(path, content)
+            .unwrap_or_else(|e| panic!("failed to write {}: {e}", path.display()));
+    };
+
+    let signature = match args.signature {
+        Some(signature) => signature,
+        None => mismatch_signature(&replay(&args.simulator, &args.trace))
+            .expect("the full trace does not fail with a data mismatch"),
+    };
+    eprintln!(
+        "shrinking {} transaction(s), failure: {signature}",
+        transactions.len()
+    );
+
+    let mut runs = 0;
+    let shrunk = ddmin(transactions, |subset| {
+        runs += 1;
+        write(&candidate, subset);
+        let fails = replay(&args.simulator, &candidate).contains(&signature);
+        eprintln!(
+            "run {runs}: {} transaction(s) {}",
+            subset.len(),
+            if fails { "fail" } else { "pass" }
+        );
+        fails
+    });
+    // ignore a candidate that was never written
+    let _ = std::fs::remove_file(&candidate);
+    write(&args.output, &shrunk);
+    eprintln!(
+        "{} transaction(s) written to {} after {runs} run(s)",
+        shrunk.len(),
+        args.output.display()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ddmin_finds_culprits() {
+        let items: Vec<u32> = (0..40).collect();
+        // the failure needs the write at 7 and the read at 31
+        let shrunk = ddmin(items, |subset| subset.contains(&7) && subset.contains(&31));
+        assert_eq!(shrunk, vec![7, 31]);
+    }
+
+    #[test]
+    fn signature_from_report() {
+        let output = "INFO checks\nERROR   [42] R: id 3 addr 0x00001000 data mismatch, expected 01 received 02\n";
+        assert_eq!(
+            mismatch_signature(output).as_deref(),
+            Some("R: id 3 addr 0x00001000 data mismatch")
+        );
+        assert_eq!(mismatch_signature("INFO checks: all passed"), None);
+    }
+}
@@ -0,0 +1,252 @@
+use std::fmt;
+use std::ops::Range;
+use tracing::{error, info};
+
+use crate::mode::ModeMismatch;
+use crate::protocol::Violation;
+use crate::refresh::RefreshViolation;
+use crate::sdram::DeviceError;
+use crate::timing::TimingViolation;
+
+/// AXI4 BRESP/RRESP encoding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Resp {
+    Okay,
+    ExOkay,
+    SlvErr,
+    DecErr,
+}
+
+impl Resp {
+    pub(crate) fn from_bits(resp: u8) -> Self {
+        match resp & 0b11 {
+            0 => Resp::Okay,
+            1 => Resp::ExOkay,
+            2 => Resp::SlvErr,
+            _ => Resp::DecErr,
+        }
+    }
+
+    pub(crate) fn is_error(self) -> bool {
+        matches!(self, Resp::SlvErr | Resp::DecErr)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Channel {
+    AW,
+    AR,
+    B,
+    R,
+}
+
+/// A DUT response that disagrees with the request it answers.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum CheckError {
+    UnknownId {
+        channel: Channel,
+        id: u8,
+    },
+    Response {
+        channel: Channel,
+        id: u8,
+        addr: u32,
+        expect_error: bool,
+        received: Resp,
+    },
+    /// RLAST asserted before `len + 1` beats
+    EarlyLast {
+        id: u8,
+        addr: u32,
+        expected: usize,
+        received: usize,
+    },
+    /// `len + 1` beats received without RLAST
+    MissingLast {
+        id: u8,
+        addr: u32,
+        expected: usize,
+    },
+    ReadData {
+        id: u8,
+        addr: u32,
+        expected: Vec<u8>,
+        received: Vec<u8>,
+    },
+    /// A request or response breaking an AXI4 rule
+    Protocol {
+        channel: Channel,
+        id: u8,
+        addr: u32,
+        violation: Violation,
+    },
+    /// A command the SDRAM chip of chip select `cs` cannot execute
+    Sdram {
+        cs: u8,
+        error: DeviceError,
+    },
+    /// A command issued to chip select `cs` before the part allows it
+    Timing {
+        cs: u8,
+        violation: TimingViolation,
+    },
+    /// A mode register or burst of chip select `cs` the controller does not expect
+    Mode {
+        cs: u8,
+        mismatch: ModeMismatch,
+    },
+    /// Rows of chip select `cs` not refreshed within tREF
+    Refresh {
+        cs: u8,
+        violation: RefreshViolation,
+    },
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckError::UnknownId { channel, id } => {
+                write!(f, "{channel:?}: response with unknown id {id}")
+            }
+            CheckError::Response {
+                channel,
+                id,
+                addr,
+                expect_error,
+                received,
+            } => write!(
+                f,
+                "{channel:?}: id {id} addr {addr:#010x} responded {received:?}, expected {}",
+                if *expect_error {
+                    "SLVERR or DECERR"
+                } else {
+                    "OKAY"
+                }
+            ),
+            CheckError::EarlyLast {
+                id,
+                addr,
+                expected,
+                received,
+            } => write!(
+                f,
+                "R: id {id} addr {addr:#010x} RLAST on beat {received}, expected {expected} beats"
+            ),
+            CheckError::MissingLast { id, addr, expected } => write!(
+                f,
+                "R: id {id} addr {addr:#010x} no RLAST after {expected} beats"
+            ),
+            CheckError::ReadData {
+                id,
+                addr,
+                expected,
+                received,
+            } => write!(
+                f,
+                "R: id {id} addr {addr:#010x} data mismatch, expected {} received {}",
+                hex::encode(expected),
+                hex::encode(received)
+            ),
+            CheckError::Protocol {
+                channel,
+                id,
+                addr,
+                violation,
+            } => write!(f, "{channel:?}: id {id} addr {addr:#010x} {violation}"),
+            CheckError::Sdram { cs, error } => write!(f, "SDRAM cs {cs}: {error}"),
+            CheckError::Timing { cs, violation } => write!(f, "SDRAM cs {cs}: {violation}"),
+            CheckError::Mode { cs, mismatch } => write!(f, "SDRAM cs {cs}: {mismatch}"),
+            CheckError::Refresh { cs, violation } => write!(f, "SDRAM cs {cs}: {violation}"),
+        }
+    }
+}
+
+/// Address windows for which the DUT is expected to answer SLVERR or DECERR.
+#[derive(Default)]
+pub(crate) struct ErrorWindows(Vec<Range<u64>>);
+
+impl ErrorWindows {
+    pub(crate) fn new(windows: Vec<Range<u64>>) -> Self {
+        ErrorWindows(windows)
+    }
+
+    /// Whether any of the `bytes` bytes starting at `addr` falls into a window.
+    pub(crate) fn expects_error(&self, addr: u32, bytes: u32) -> bool {
+        let start = addr as u64;
+        let end = start + bytes as u64;
+        self.0
+            .iter()
+            .any(|window| window.start < end && start < window.end)
+    }
+}
+
+/// Parse a window given as `<start>..<end>`, accepting `0x` hex bounds.
+pub(crate) fn parse_window(input: &str) -> Result<Range<u64>, String> {
+    let parse = |s: &str| {
+        let s = s.trim();
+        match s.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => s.parse(),
+        }
+        .map_err(|e| format!("invalid address `{s}`: {e}"))
+    };
+    let (start, end) = input
+        .split_once("..")
+        .ok_or_else(|| format!("expected `<start>..<end>`, got `{input}`"))?;
+    let (start, end) = (parse(start)?, parse(end)?);
+    if start >= end {
+        return Err(format!("empty window `{input}`"));
+    }
+    Ok(start..end)
+}
+
+/// Every check failure seen during the run, with the tick it was detected at.
+#[derive(Default)]
+pub(crate) struct CheckReport {
+    failures: Vec<(u64, CheckError)>,
+}
+
+impl CheckReport {
+    pub(crate) fn record(&mut self, tick: u64, failure: CheckError) {
+        error!("[{tick}] {failure}");
+        self.failures.push((tick, failure));
+    }
+
+    pub(crate) fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    pub(crate) fn report(&self) {
+        if self.passed() {
+            info!("checks: all passed");
+            return;
+        }
+        error!("checks: {} failure(s)", self.failures.len());
+        for (tick, failure) in &self.failures {
+            error!("  [{tick}] {failure}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_windows_overlap() {
+        let windows = ErrorWindows::new(vec![0x1000..0x2000, 0x8000..0x8004]);
+        assert!(!windows.expects_error(0x0ff0, 0x10));
+        assert!(windows.expects_error(0x0ff0, 0x11));
+        assert!(windows.expects_error(0x1ffc, 4));
+        assert!(!windows.expects_error(0x2000, 4));
+        assert!(windows.expects_error(0x8003, 1));
+    }
+
+    #[test]
+    fn window_parsing() {
+        assert_eq!(parse_window("0x1000..0x2000"), Ok(0x1000..0x2000));
+        assert_eq!(parse_window("16..32"), Ok(16..32));
+        assert!(parse_window("0x2000..0x1000").is_err());
+        assert!(parse_window("0x1000").is_err());
+    }
+}
@@ -0,0 +1,36 @@
+//! Logs of the commands sent to the SDRAM chips, one command per line:
+//!
+//! ```text
+//! # W9825G6KH at 50 MHz
+//! 104 cs0 PRECHARGE ALL
+//! 112 cs0 LMR CL=2 BL=2 BT=seq WB=burst
+//! 130 cs0 ACTIVE bank=1 row=0x1a3
+//! 132 cs0 READ bank=1 col=0x40 AP=0
+//! ```
+//!
+//! Each line is the tick the command was registered at, its chip select and
+//! the command decoded from the pins. NOP and DESELECT are left out.
+
+use std::fs::File;
+use std::io::{self, LineWriter, Write};
+use std::path::Path;
+
+use crate::sdram::Command;
+use common::sdram_part::SdramPart;
+
+pub(crate) struct CommandLog {
+    // line buffered, so the log survives a simulator that is killed
+    file: LineWriter<File>,
+}
+
+impl CommandLog {
+    pub(crate) fn create(path: &Path, part: &SdramPart) -> io::Result<Self> {
+        let mut file = LineWriter::new(File::create(path)?);
+        writeln!(file, "# {} at {} MHz", part.name, part.timing.clock_mhz)?;
+        Ok(CommandLog { file })
+    }
+
+    pub(crate) fn record(&mut self, tick: u64, cs: u8, command: &Command) {
+        writeln!(self.file, "{tick} cs{cs} {command}").expect("failed to write SDRAM command log");
+    }
+}
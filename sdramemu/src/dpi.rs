@@ -3,14 +3,20 @@
 
 use bytemuck::cast_slice;
 use clap::Parser;
+use rand::distributions::{Distribution, WeightedIndex};
 use rand::Rng;
 use std::ffi::*;
+use std::ops::Range;
 use std::sync::Mutex;
 use tracing::debug;
 
 use crate::drive::Driver;
+use crate::registry::Registry;
+use crate::sdram::Pins;
+use crate::{OfflineArgs, AXI_WRITE_PAYLOAD_SIZE};
+use common::constraints::{AddressWindow, Constraints};
+use common::rtl_config::{AXIParameter, RTLConfig};
 use svdpi::SvScope;
-use crate::{OfflineArgs, AXI_SIZE};
 
 pub type SvBitVecVal = u32;
 
@@ -18,16 +24,190 @@ pub type SvBitVecVal = u32;
 // preparing data structures
 // --------------------------
 
-static DPI_TARGET: Mutex<Option<Box<Driver>>> = Mutex::new(None);
-static AWID: Mutex<u8> = Mutex::new(0);
+static REGISTRY: Mutex<Option<Registry>> = Mutex::new(None);
 
-#[derive(Clone, Debug)]
+/// Field widths of the payload bundles generated by `AXI4MasterAgent`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct PayloadLayout {
+    pub(crate) addr_width: u32,
+    pub(crate) id_width: u32,
+    pub(crate) data_width: u32,
+    pub(crate) aw_user_width: u32,
+    pub(crate) w_user_width: u32,
+    pub(crate) ar_user_width: u32,
+    // number of beats a single `WritePayload` can carry
+    pub(crate) write_payload_size: usize,
+}
+
+impl PayloadLayout {
+    pub(crate) fn new(axi: &AXIParameter) -> Self {
+        PayloadLayout {
+            addr_width: axi.addr_width,
+            id_width: axi.id_width,
+            data_width: axi.data_width,
+            aw_user_width: axi.aw_user_width(),
+            w_user_width: axi.w_user_width(),
+            ar_user_width: axi.ar_user_width(),
+            write_payload_size: AXI_WRITE_PAYLOAD_SIZE,
+        }
+    }
+
+    // id and user fields are padded to at least 8 bits for a simple C-API
+    fn id_bits(&self) -> u32 {
+        self.id_width.max(8)
+    }
+
+    fn ar_user_bits(&self) -> u32 {
+        self.ar_user_width.max(8)
+    }
+
+    fn aw_user_bits(&self) -> u32 {
+        self.aw_user_width.max(8)
+    }
+
+    fn w_user_bits(&self) -> u32 {
+        self.w_user_width.max(8)
+    }
+
+    // strb is aligned to u8 for dataWidth <= 8
+    fn strb_bits(&self) -> u32 {
+        (self.data_width / 8).max(8)
+    }
+
+    /// Width of `WritePayload` in bits.
+    pub(crate) fn write_payload_width(&self) -> u32 {
+        let beats = self.write_payload_size as u32;
+        self.id_bits()
+            + 8
+            + self.addr_width
+            + beats * (self.data_width + self.strb_bits() + self.w_user_bits())
+            + self.aw_user_bits()
+            + 1
+            + 7 * 8
+    }
+
+    /// Width of `ReadAddressPayload` in bits.
+    pub(crate) fn read_payload_width(&self) -> u32 {
+        self.addr_width + self.id_bits() + self.ar_user_bits() + 8 * 8 + 1
+    }
+}
+
+/// Number of `SvBitVecVal` words holding a bit vector of `width` bits.
+fn sv_words(width: u32) -> usize {
+    width.div_ceil(SvBitVecVal::BITS) as usize
+}
+
+/// Serializes fields into an SV bit vector the way Chisel flattens a `Bundle`:
+/// the first field takes the most significant bits.
+pub(crate) struct BundlePacker<'a> {
+    dst: &'a mut [SvBitVecVal],
+    cursor: u32,
+}
+
+impl<'a> BundlePacker<'a> {
+    pub(crate) fn new(dst: &'a mut [SvBitVecVal], width: u32) -> Self {
+        assert!(
+            dst.len() >= sv_words(width),
+            "bit vector too short for {width} bits"
+        );
+        dst.fill(0);
+        BundlePacker { dst, cursor: width }
+    }
+
+    /// Append a field of `width` bits taken from little-endian `bytes`,
+    /// zero-extending or truncating as needed.
+    pub(crate) fn bytes(&mut self, bytes: &[u8], width: u32) -> &mut Self {
+        assert!(self.cursor >= width, "bundle overflow");
+        self.cursor -= width;
+        for bit in 0..width {
+            let byte = bytes.get((bit / 8) as usize).copied().unwrap_or(0);
+            if (byte >> (bit % 8)) & 1 != 0 {
+                let pos = self.cursor + bit;
+                self.dst[(pos / SvBitVecVal::BITS) as usize] |= 1 << (pos % SvBitVecVal::BITS);
+            }
+        }
+        self
+    }
+
+    pub(crate) fn field(&mut self, value: u64, width: u32) -> &mut Self {
+        self.bytes(&value.to_le_bytes(), width)
+    }
+
+    pub(crate) fn finish(&self) {
+        assert_eq!(
+            self.cursor, 0,
+            "bundle underflow: {} bits left",
+            self.cursor
+        );
+    }
+}
+
+/// The layout the unit tests default to: 32-bit address and data, 4-bit id,
+/// no user bits and one beat per `WritePayload`.
+#[cfg(test)]
+pub(crate) fn test_layout() -> PayloadLayout {
+    PayloadLayout {
+        addr_width: 32,
+        id_width: 4,
+        data_width: 32,
+        aw_user_width: 0,
+        w_user_width: 0,
+        ar_user_width: 0,
+        write_payload_size: 1,
+    }
+}
+
+/// Inverse of [`BundlePacker`].
+#[cfg(test)]
+pub(crate) struct BundleUnpacker<'a> {
+    src: &'a [SvBitVecVal],
+    cursor: u32,
+}
+
+#[cfg(test)]
+impl<'a> BundleUnpacker<'a> {
+    pub(crate) fn new(src: &'a [SvBitVecVal], width: u32) -> Self {
+        assert!(
+            src.len() >= sv_words(width),
+            "bit vector too short for {width} bits"
+        );
+        BundleUnpacker { src, cursor: width }
+    }
+
+    /// Take a field of `width` bits as little-endian bytes.
+    pub(crate) fn bytes(&mut self, width: u32) -> Vec<u8> {
+        assert!(self.cursor >= width, "bundle overflow");
+        self.cursor -= width;
+        let mut bytes = vec![0u8; width.div_ceil(8) as usize];
+        for bit in 0..width {
+            let pos = self.cursor + bit;
+            if (self.src[(pos / SvBitVecVal::BITS) as usize] >> (pos % SvBitVecVal::BITS)) & 1 != 0
+            {
+                bytes[(bit / 8) as usize] |= 1 << (bit % 8);
+            }
+        }
+        bytes
+    }
+
+    /// Take a field of `width` bits, keeping only the low 64 bits.
+    pub(crate) fn field(&mut self, width: u32) -> u64 {
+        let mut buf = [0u8; 8];
+        for (dst, src) in buf.iter_mut().zip(self.bytes(width)) {
+            *dst = src;
+        }
+        u64::from_le_bytes(buf)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub(crate) struct AxiWritePayload {
     pub(crate) id: u8,
     pub(crate) len: u8,
     pub(crate) addr: u32,
-    pub(crate) data: Vec<u32>,
-    pub(crate) strb: Vec<u8>,
+    // one little-endian bus-wide word per beat
+    pub(crate) data: Vec<Vec<u8>>,
+    // one lane mask per beat, bit i enables byte lane i
+    pub(crate) strb: Vec<u128>,
     pub(crate) wUser: Vec<u32>,
     pub(crate) awUser: u32,
     pub(crate) dataValid: bool,
@@ -40,27 +220,198 @@ pub(crate) struct AxiWritePayload {
     pub(crate) size: u8,
 }
 
+/// Byte lanes driven by one beat of a burst.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct BeatLanes {
+    /// address of byte lane 0
+    pub(crate) base: u32,
+    pub(crate) lanes: Range<u32>,
+}
+
+impl BeatLanes {
+    /// WSTRB enabling every active lane.
+    pub(crate) fn strobe(&self) -> u128 {
+        self.lanes.clone().fold(0, |strb, lane| strb | 1 << lane)
+    }
+}
+
+/// Byte lanes of every beat of a burst on a `bus_bytes` wide bus, narrow
+/// transfers move through the lanes with their address. Only FIXED and INCR
+/// bursts may start unaligned, where the first beat carries fewer bytes.
+pub(crate) fn burst_lanes(
+    addr: u32,
+    burst: u8,
+    len: u8,
+    size: u8,
+    bus_bytes: u32,
+) -> Vec<BeatLanes> {
+    // a transfer wider than the bus, flagged by the protocol checker, moves
+    // one bus width per beat
+    let bytes_number: u32 = (1u32 << size).min(bus_bytes);
+    let transfer_count = len as u32 + 1;
+    let wrap_bytes = bytes_number * transfer_count;
+    let lower_boundary = addr / wrap_bytes * wrap_bytes;
+    let mut current_addr = addr;
+    (0..transfer_count)
+        .map(|_| {
+            // an unaligned address drops the lanes below it, up to the end
+            // of the aligned transfer (AXI4 A3.4.1)
+            let aligned_addr = current_addr / bytes_number * bytes_number;
+            let lane = current_addr % bus_bytes;
+            let beat = BeatLanes {
+                base: current_addr - lane,
+                lanes: lane..(aligned_addr % bus_bytes + bytes_number).min(bus_bytes),
+            };
+            current_addr = match burst {
+                0 => current_addr, // FIXED
+                2 => {
+                    if current_addr + bytes_number >= lower_boundary + wrap_bytes {
+                        lower_boundary
+                    } else {
+                        current_addr + bytes_number
+                    }
+                } // WRAP
+                // INCR, and the reserved type, flagged by the protocol
+                // checker, which the controller steps like INCR
+                _ => aligned_addr.wrapping_add(bytes_number),
+            };
+            beat
+        })
+        .collect()
+}
+
+/// Start address of a burst that stays within one of the address windows.
+fn random_addr(
+    layout: &PayloadLayout,
+    constraints: &Constraints,
+    rng: &mut impl Rng,
+    burst: u8,
+    beats: u8,
+    size: u8,
+) -> u32 {
+    let bytes_number = 1u64 << size;
+    let total_bytes = bytes_number * beats as u64;
+    // bytes covered by the burst and the alignment of the range they start in
+    let (span, align) = match burst {
+        0 => (bytes_number, bytes_number),
+        1 => (total_bytes, bytes_number),
+        _ => (total_bytes, total_bytes),
+    };
+    let addr_space = 1u64 << layout.addr_width.min(32);
+    let whole = [AddressWindow {
+        start: 0,
+        end: addr_space,
+    }];
+    let windows = match constraints.address_windows.as_slice() {
+        [] => &whole[..],
+        windows => windows,
+    };
+    let out_of_range = !constraints.out_of_range_windows.is_empty()
+        && constraints.out_of_range > 0.0
+        && rng.gen_bool(constraints.out_of_range);
+    let windows = if out_of_range {
+        &constraints.out_of_range_windows[..]
+    } else {
+        windows
+    };
+    // aligned starts that keep the burst inside each window
+    let starts: Vec<(u64, u64)> = windows
+        .iter()
+        .map(|window| {
+            let first = window.start.div_ceil(align) * align;
+            let last = window.end.min(addr_space).saturating_sub(span);
+            (first, last)
+        })
+        .filter(|(first, last)| first <= last)
+        .collect();
+    assert!(
+        !starts.is_empty(),
+        "no address window fits a burst of {span} bytes"
+    );
+    let (first, last) = starts[rng.gen_range(0..starts.len())];
+    let mut base = first + rng.gen_range(0..=(last - first) / align) * align;
+    // INCR bursts must not cross a 4 KiB boundary, end right before it instead
+    let boundary = (base | 0xfff) + 1;
+    if burst == 1 && base + span > boundary && boundary - span >= first {
+        base = (boundary - span) / align * align;
+    }
+    let addr = if burst == 2 {
+        // any beat of the wrap container may start the burst
+        base + rng.gen_range(0..beats as u64) * bytes_number
+    } else if bytes_number > 1 && rng.gen_bool(constraints.unaligned) {
+        base + rng.gen_range(1..bytes_number)
+    } else {
+        base
+    };
+    addr as u32
+}
+
 impl AxiWritePayload {
-    pub(crate) fn random() -> Self {
-        let mut rng = rand::thread_rng();
-        let burst_type = rng.gen_range(0..=2);
-        let burst_length = if burst_type == 2 {
-            rng.gen_range(2..=8) * 2
+    pub(crate) fn random(
+        layout: &PayloadLayout,
+        constraints: &Constraints,
+        rng: &mut impl Rng,
+        id: u8,
+    ) -> Self {
+        // the whole burst has to fit into a single WritePayload
+        let max_beats = constraints
+            .beats
+            .max
+            .min(layout.write_payload_size.min(16) as u8);
+        let min_beats = constraints.beats.min.min(max_beats);
+        // WRAP needs a power of two of at least 2 beats within the range
+        let wrap_beats: Vec<u8> = [2, 4, 8, 16]
+            .into_iter()
+            .filter(|beats| (min_beats..=max_beats).contains(beats))
+            .collect();
+        let weights = &constraints.burst_weights;
+        let burst_weights = [
+            weights.fixed,
+            weights.incr,
+            if wrap_beats.is_empty() {
+                0
+            } else {
+                weights.wrap
+            },
+        ];
+        let burst_type = WeightedIndex::new(burst_weights)
+            .expect("no burst type can be generated within the constraints")
+            .sample(rng) as u8;
+        let burst_beats: u8 = if burst_type == 2 {
+            wrap_beats[rng.gen_range(0..wrap_beats.len())]
         } else {
-            rng.gen_range(0..=15)
+            rng.gen_range(min_beats..=max_beats)
         };
-        let burst_size: u8 = rng.gen_range(0..=7 - AXI_SIZE.leading_zeros()) as u8;
-        let bytes_number = 1 << burst_size;
-        *AWID.lock().unwrap() += 1;
+        let bus_bytes = layout.data_width / 8;
+        let max_size = constraints.size.max.min(bus_bytes.ilog2() as u8);
+        let burst_size: u8 = rng.gen_range(constraints.size.min.min(max_size)..=max_size);
+        let addr = random_addr(
+            layout,
+            constraints,
+            rng,
+            burst_type,
+            burst_beats,
+            burst_size,
+        );
         AxiWritePayload {
-            id: *AWID.lock().unwrap(),
-            len: burst_length,
-            addr: rng.gen_range(0..=u32::MAX) / bytes_number * bytes_number,
-            data: (0..burst_length)
-                .map(|_| rng.gen_range(0..=u32::MAX))
+            id,
+            len: burst_beats - 1,
+            addr,
+            data: (0..burst_beats)
+                .map(|_| (0..bus_bytes).map(|_| rng.gen()).collect())
                 .collect(),
-            strb: (0..burst_length).map(|_| (1 << burst_size) - 1).collect(),
-            wUser: (0..burst_length)
+            strb: burst_lanes(addr, burst_type, burst_beats - 1, burst_size, bus_bytes)
+                .into_iter()
+                .map(|beat| {
+                    if constraints.strobe_density >= 1.0 {
+                        return beat.strobe();
+                    }
+                    beat.lanes
+                        .filter(|_| rng.gen_bool(constraints.strobe_density))
+                        .fold(0, |strb, lane| strb | 1 << lane)
+                })
+                .collect(),
+            wUser: (0..burst_beats)
                 .map(|_| rng.gen_range(0..=u32::MAX))
                 .collect(),
             awUser: rng.gen_range(0..=u32::MAX),
@@ -74,9 +425,107 @@ impl AxiWritePayload {
             size: burst_size,
         }
     }
+
+    /// Serialize into the layout of the Chisel `WritePayload` bundle, padding
+    /// unused beats with zeros.
+    pub(crate) fn pack(&self, layout: &PayloadLayout, dst: &mut [SvBitVecVal]) {
+        let beats = layout.write_payload_size;
+        assert!(
+            self.data.len() <= beats && self.strb.len() <= beats && self.wUser.len() <= beats,
+            "burst of {} beats does not fit into a WritePayload of {beats} beats",
+            self.data.len()
+        );
+
+        let mut packer = BundlePacker::new(dst, layout.write_payload_width());
+        packer
+            .field(self.id as u64, layout.id_bits())
+            .field(self.len as u64, 8)
+            .field(self.addr as u64, layout.addr_width);
+        // a Chisel Vec puts its last element in the most significant bits
+        for beat in (0..beats).rev() {
+            let data = self.data.get(beat).map(Vec::as_slice).unwrap_or_default();
+            packer.bytes(data, layout.data_width);
+        }
+        for beat in (0..beats).rev() {
+            let strb = self.strb.get(beat).copied().unwrap_or(0);
+            packer.bytes(&strb.to_le_bytes(), layout.strb_bits());
+        }
+        for beat in (0..beats).rev() {
+            let user = self.wUser.get(beat).copied().unwrap_or(0);
+            packer.field(user as u64, layout.w_user_bits());
+        }
+        packer
+            .field(self.awUser as u64, layout.aw_user_bits())
+            .field(self.dataValid as u64, 1)
+            .field(self.burst as u64, 8)
+            .field(self.cache as u64, 8)
+            .field(self.lock as u64, 8)
+            .field(self.prot as u64, 8)
+            .field(self.qos as u64, 8)
+            .field(self.region as u64, 8)
+            .field(self.size as u64, 8)
+            .finish();
+    }
+
+    /// Inverse of [`AxiWritePayload::pack`], yielding exactly `len + 1` beats.
+    #[cfg(test)]
+    pub(crate) fn unpack(layout: &PayloadLayout, src: &[SvBitVecVal]) -> Self {
+        let beats = layout.write_payload_size;
+        let mut unpacker = BundleUnpacker::new(src, layout.write_payload_width());
+        let id = unpacker.field(layout.id_bits()) as u8;
+        let len = unpacker.field(8) as u8;
+        let addr = unpacker.field(layout.addr_width) as u32;
+        fn burst<T>(mut beats: Vec<T>, len: u8) -> Vec<T> {
+            // a Chisel Vec puts its last element in the most significant bits
+            beats.reverse();
+            beats.truncate(len as usize + 1);
+            beats
+        }
+        let data = burst(
+            (0..beats)
+                .map(|_| unpacker.bytes(layout.data_width))
+                .collect(),
+            len,
+        );
+        let strb = burst(
+            (0..beats)
+                .map(|_| {
+                    let mut lanes = [0u8; 16];
+                    for (dst, src) in lanes.iter_mut().zip(unpacker.bytes(layout.strb_bits())) {
+                        *dst = src;
+                    }
+                    u128::from_le_bytes(lanes)
+                })
+                .collect(),
+            len,
+        );
+        let wUser = burst(
+            (0..beats)
+                .map(|_| unpacker.field(layout.w_user_bits()) as u32)
+                .collect(),
+            len,
+        );
+        AxiWritePayload {
+            id,
+            len,
+            addr,
+            data,
+            strb,
+            wUser,
+            awUser: unpacker.field(layout.aw_user_bits()) as u32,
+            dataValid: unpacker.field(1) != 0,
+            burst: unpacker.field(8) as u8,
+            cache: unpacker.field(8) as u8,
+            lock: unpacker.field(8) as u8,
+            prot: unpacker.field(8) as u8,
+            qos: unpacker.field(8) as u8,
+            region: unpacker.field(8) as u8,
+            size: unpacker.field(8) as u8,
+        }
+    }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub(crate) struct AxiReadPayload {
     pub(crate) addr: u32,
     pub(crate) id: u8,
@@ -93,23 +542,6 @@ pub(crate) struct AxiReadPayload {
 }
 
 impl AxiReadPayload {
-    pub(crate) fn random() -> Self {
-        let mut rng = rand::thread_rng();
-        AxiReadPayload {
-            addr: rng.gen_range(0..=255),
-            id: rng.gen_range(0..=255),
-            user: rng.gen_range(0..=255),
-            burst: rng.gen_range(0..=15),
-            cache: rng.gen_range(0..=15),
-            len: rng.gen_range(0..=255),
-            lock: rng.gen_range(0..=1),
-            prot: rng.gen_range(0..=7),
-            qos: rng.gen_range(0..=15),
-            region: rng.gen_range(0..=15),
-            size: rng.gen_range(0..=15),
-            valid: true,
-        }
-    }
     pub(crate) fn from_write_payload(payload: AxiWritePayload) -> Self {
         AxiReadPayload {
             addr: payload.addr,
@@ -126,83 +558,210 @@ impl AxiReadPayload {
             valid: true,
         }
     }
+
+    /// Serialize into the layout of the Chisel `ReadAddressPayload` bundle.
+    pub(crate) fn pack(&self, layout: &PayloadLayout, dst: &mut [SvBitVecVal]) {
+        let mut packer = BundlePacker::new(dst, layout.read_payload_width());
+        packer
+            .field(self.addr as u64, layout.addr_width)
+            .field(self.id as u64, layout.id_bits())
+            .field(self.user as u64, layout.ar_user_bits())
+            .field(self.burst as u64, 8)
+            .field(self.cache as u64, 8)
+            .field(self.len as u64, 8)
+            .field(self.lock as u64, 8)
+            .field(self.prot as u64, 8)
+            .field(self.qos as u64, 8)
+            .field(self.region as u64, 8)
+            .field(self.size as u64, 8)
+            .field(self.valid as u64, 1)
+            .finish();
+    }
+
+    #[cfg(test)]
+    pub(crate) fn unpack(layout: &PayloadLayout, src: &[SvBitVecVal]) -> Self {
+        let mut unpacker = BundleUnpacker::new(src, layout.read_payload_width());
+        AxiReadPayload {
+            addr: unpacker.field(layout.addr_width) as u32,
+            id: unpacker.field(layout.id_bits()) as u8,
+            user: unpacker.field(layout.ar_user_bits()) as u32,
+            burst: unpacker.field(8) as u8,
+            cache: unpacker.field(8) as u8,
+            len: unpacker.field(8) as u8,
+            lock: unpacker.field(8) as u8,
+            prot: unpacker.field(8) as u8,
+            qos: unpacker.field(8) as u8,
+            region: unpacker.field(8) as u8,
+            size: unpacker.field(8) as u8,
+            valid: unpacker.field(1) != 0,
+        }
+    }
 }
 
-unsafe fn write_to_pointer(dst: *mut u8, data: &[u8]) {
-    let dst = std::slice::from_raw_parts_mut(dst, data.len());
-    dst.copy_from_slice(data);
+/// Copy an SV bit vector of `width` bits into little-endian bytes.
+unsafe fn read_bit_vec(src: *const SvBitVecVal, width: u32) -> Vec<u8> {
+    let src = std::slice::from_raw_parts(src, sv_words(width));
+    let mut bytes: Vec<u8> = cast_slice(src).to_vec();
+    bytes.truncate(width.div_ceil(8) as usize);
+    bytes
 }
 
-unsafe fn fill_axi_read_payload(dst: *mut SvBitVecVal, dlen: u32, payload: &AxiReadPayload) {}
+// an all-zero payload has valid/dataValid cleared, so the agent issues nothing
+unsafe fn fill_axi_read_payload(
+    dst: *mut SvBitVecVal,
+    layout: &PayloadLayout,
+    payload: Option<&AxiReadPayload>,
+) {
+    let dst = std::slice::from_raw_parts_mut(dst, sv_words(layout.read_payload_width()));
+    match payload {
+        Some(payload) => payload.pack(layout, dst),
+        None => dst.fill(0),
+    }
+}
 
-unsafe fn fill_axi_write_payload(dst: *mut SvBitVecVal, dlen: u32, payload: &AxiWritePayload) {
-    let data_len = 256 * (dlen / 8) as usize;
-    assert!(payload.data.len() <= data_len);
-    write_to_pointer(dst as *mut u8, cast_slice(&payload.data));
+unsafe fn fill_axi_write_payload(
+    dst: *mut SvBitVecVal,
+    layout: &PayloadLayout,
+    payload: Option<&AxiWritePayload>,
+) {
+    let dst = std::slice::from_raw_parts_mut(dst, sv_words(layout.write_payload_width()));
+    match payload {
+        Some(payload) => payload.pack(layout, dst),
+        None => dst.fill(0),
+    }
 }
 
 //----------------------
 // dpi functions
 //----------------------
 
-/// evaluate at R fire.
-#[no_mangle]
-unsafe extern "C" fn axi_read_resp_axi4Probe(
-    rdata: c_longlong,
-    rid: c_uchar,
-    rlast: c_uchar,
-    rresp: c_uchar,
-    ruser: c_uchar,
-) {
-    debug!(
-        "axi_read_resp_axi4Probe (rdata={rdata}, rid={rid}, rlast={rlast:#x}, \
-  rresp={rresp}, ruser={ruser})"
-    );
-    let mut driver = DPI_TARGET.lock().unwrap();
-    let driver = driver.as_mut().unwrap();
-    driver.axi_read_resp(
-        rdata as u32,
-        rid as u8,
-        rlast as u8,
-        rresp as u8,
-        ruser as u8,
-    );
+fn with_agent<R>(name: &str, f: impl FnOnce(&mut Driver) -> R) -> R {
+    let mut registry = REGISTRY.lock().unwrap();
+    let driver = registry
+        .as_mut()
+        .expect("cosim_init was not called")
+        .agent(name);
+    let span = driver.span.clone();
+    span.in_scope(|| f(driver))
 }
 
-/// evaluate at AW ready.
-#[no_mangle]
-unsafe extern "C" fn axi_write_ready_axi4Probe(payload: *mut SvBitVecVal) {
-    debug!("axi_write_ready_axi4Probe");
-    let mut driver = DPI_TARGET.lock().unwrap();
-    let driver = driver.as_mut().unwrap();
-    let response = driver.axi_write_ready();
-    fill_axi_write_payload(payload, driver.dlen, &response);
+/// Export the DPI functions of every `AXI4MasterAgent` named `$agent`, and
+/// list the names in [`AGENTS`].
+macro_rules! axi_agent_dpi {
+    ($(
+        $agent:literal:
+            $read_resp:ident,
+            $write_ready:ident,
+            $write_done:ident,
+            $read_ready:ident;
+    )*) => {
+        /// Agents whose DPI functions are exported, the names `--agent` accepts.
+        pub(crate) const AGENTS: &[&str] = &[$($agent),*];
+
+        $(
+        /// evaluate at R fire, `rdata` is padded to `dataWidth + 1` bits so it is
+        /// passed as a bit vector at every bus width.
+        #[no_mangle]
+        unsafe extern "C" fn $read_resp(
+            rdata: *const SvBitVecVal,
+            rid: c_uchar,
+            rlast: c_uchar,
+            rresp: c_uchar,
+            ruser: c_uchar,
+        ) {
+            with_agent($agent, |driver| {
+                let rdata = read_bit_vec(rdata, driver.layout.data_width);
+                debug!(
+                    "{} (rdata={}, rid={rid}, rlast={rlast:#x}, \
+  rresp={rresp}, ruser={ruser})",
+                    stringify!($read_resp),
+                    hex::encode(&rdata)
+                );
+                driver.axi_read_resp(&rdata, rid, rlast, rresp, ruser);
+            })
+        }
+
+        /// evaluate at AW ready, with the `channelId` of the agent.
+        #[no_mangle]
+        unsafe extern "C" fn $write_ready(channel_id: c_ulonglong, payload: *mut SvBitVecVal) {
+            debug!(stringify!($write_ready));
+            with_agent($agent, |driver| {
+                driver.check_channel_id(channel_id);
+                let response = driver.axi_write_ready();
+                fill_axi_write_payload(payload, &driver.layout, response.as_ref());
+            })
+        }
+
+        /// evaluate at B fire.
+        #[no_mangle]
+        unsafe extern "C" fn $write_done(bid: c_uchar, bresp: c_uchar, buser: c_uchar) {
+            debug!(
+                "{} (bid={bid}, bresp={bresp}, buser={buser})",
+                stringify!($write_done)
+            );
+            with_agent($agent, |driver| driver.axi_write_done(bid, bresp, buser))
+        }
+
+        /// evaluate at AR ready, with the `channelId` of the agent.
+        #[no_mangle]
+        unsafe extern "C" fn $read_ready(channel_id: c_ulonglong, payload: *mut SvBitVecVal) {
+            debug!(stringify!($read_ready));
+            with_agent($agent, |driver| {
+                driver.check_channel_id(channel_id);
+                let response = driver.axi_read_ready();
+                fill_axi_read_payload(payload, &driver.layout, response.as_ref());
+            })
+        }
+        )*
+    };
 }
 
-/// evaluate at B fire.
-#[no_mangle]
-unsafe extern "C" fn axi_write_done_axi4Probe(bid: c_uchar, bresp: c_uchar, buser: c_uchar) {
-    debug!("axi_write_ready_axi4Probe (bid={bid}, bresp={bresp}, buser={buser})");
-    let mut driver = DPI_TARGET.lock().unwrap();
-    let driver = driver.as_mut().unwrap();
-    driver.axi_write_done(bid as u8, bresp as u8, buser as u8);
+// one line per AXI4MasterAgent instance of the testbench
+axi_agent_dpi! {
+    "axi4Probe":
+        axi_read_resp_axi4Probe,
+        axi_write_ready_axi4Probe,
+        axi_write_done_axi4Probe,
+        axi_read_ready_axi4Probe;
 }
 
-/// evaluate at AW ready.
+/// evaluate at every rising edge of the clock of the SDRAM chip `cs`, every
+/// pin is widened to 8, 16 or 32 bits and passed by value.
 #[no_mangle]
-unsafe extern "C" fn axi_read_ready_axi4Probe(payload: *mut SvBitVecVal) {
-    debug!("axi_read_ready_axi4Probe");
-    let mut driver = DPI_TARGET.lock().unwrap();
-    let driver = driver.as_mut().unwrap();
-    let response = driver.axi_read_ready();
-    fill_axi_read_payload(payload, driver.dlen, &response);
+unsafe extern "C" fn sdram_clock(
+    cs: c_uchar,
+    cke: c_uchar,
+    cs_n: c_uchar,
+    ras_n: c_uchar,
+    cas_n: c_uchar,
+    we_n: c_uchar,
+    addr: c_ushort,
+    bs: c_uchar,
+    dqm: c_uchar,
+    dq_i: c_uint,
+    dq_o: *mut c_uint,
+) {
+    let mut registry = REGISTRY.lock().unwrap();
+    let registry = registry.as_mut().expect("cosim_init was not called");
+    let pins = Pins {
+        cke: cke != 0,
+        cs_n: cs_n != 0,
+        ras_n: ras_n != 0,
+        cas_n: cas_n != 0,
+        we_n: we_n != 0,
+        addr,
+        bs,
+        dqm,
+        dq: dq_i,
+    };
+    *dq_o = registry.sdram_clock(cs, &pins);
 }
 
 #[no_mangle]
 unsafe extern "C" fn cosim_watchdog(reason: *mut c_char) {
-    let mut driver = DPI_TARGET.lock().unwrap();
-    if let Some(driver) = driver.as_mut() {
-        *reason = driver.watchdog() as c_char;
+    let mut registry = REGISTRY.lock().unwrap();
+    if let Some(registry) = registry.as_mut() {
+        *reason = registry.watchdog() as c_char;
     }
 }
 
@@ -212,20 +771,13 @@ unsafe extern "C" fn cosim_init() {
 
     let args = OfflineArgs::parse();
     args.common_args.setup_logger().unwrap();
+    let config = RTLConfig::load(&args.common_args.config).unwrap();
 
     let scope = SvScope::get_current().expect("failed to get scope in cosim_init");
 
-    let driver = Box::new(Driver::new(scope, &args));
-    let mut dpi_target = DPI_TARGET.lock().unwrap();
-    assert!(
-        dpi_target.is_none(),
-        "cosim_init should be called only once"
-    );
-    *dpi_target = Some(driver);
-
-    if let Some(driver) = dpi_target.as_mut() {
-        driver.init();
-    }
+    let mut registry = REGISTRY.lock().unwrap();
+    assert!(registry.is_none(), "cosim_init should be called only once");
+    registry.insert(Registry::new(scope, &args, &config)).init();
 }
 
 //--------------------------------
@@ -233,6 +785,7 @@ unsafe extern "C" fn cosim_init() {
 //--------------------------------
 
 mod dpi_export {
+    #[cfg(feature = "trace")]
     use std::ffi::*;
 
     extern "C" {
@@ -252,3 +805,246 @@ pub(crate) fn dump_wave(scope: svdpi::SvScope, path: &str) {
         dpi_export::dump_wave(path_cstring.as_ptr());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // matches configs/SDRAMControllerTestBenchMain.json
+
+    fn sample_read_payload() -> AxiReadPayload {
+        AxiReadPayload {
+            addr: 0xdead_beef,
+            id: 0x5,
+            user: 0xa5,
+            burst: 1,
+            cache: 0x3,
+            len: 15,
+            lock: 1,
+            prot: 0x6,
+            qos: 0x9,
+            region: 0xc,
+            size: 2,
+            valid: true,
+        }
+    }
+
+    #[test]
+    fn read_payload_round_trip() {
+        let layout = test_layout();
+        let payload = sample_read_payload();
+        let mut buf = vec![0; sv_words(layout.read_payload_width())];
+        payload.pack(&layout, &mut buf);
+        assert_eq!(AxiReadPayload::unpack(&layout, &buf), payload);
+    }
+
+    #[test]
+    fn read_payload_bit_layout() {
+        let layout = test_layout();
+        // 32 + 8 + 8 + 8 * 8 + 1
+        assert_eq!(layout.read_payload_width(), 113);
+
+        let payload = sample_read_payload();
+        let mut buf = vec![0; 4];
+        payload.pack(&layout, &mut buf);
+        // valid is the LSB, followed by size, region, ...
+        assert_eq!(buf[0] & 1, 1);
+        assert_eq!((buf[0] >> 1) & 0xff, 2);
+        assert_eq!((buf[0] >> 9) & 0xff, 0xc);
+        // addr occupies the top 32 bits: [112:81]
+        let addr = (buf[2] >> 17) as u64 | (buf[3] as u64) << 15;
+        assert_eq!(addr, 0xdead_beef);
+    }
+
+    #[test]
+    fn read_payload_wide_fields() {
+        let layout = PayloadLayout {
+            addr_width: 40,
+            id_width: 12,
+            ar_user_width: 16,
+            ..test_layout()
+        };
+        let payload = AxiReadPayload {
+            user: 0xbeef,
+            ..sample_read_payload()
+        };
+        let mut buf = vec![0; sv_words(layout.read_payload_width())];
+        payload.pack(&layout, &mut buf);
+        assert_eq!(AxiReadPayload::unpack(&layout, &buf), payload);
+    }
+
+    fn sample_write_payload(beats: usize) -> AxiWritePayload {
+        AxiWritePayload {
+            id: 0x3,
+            len: beats as u8 - 1,
+            addr: 0x1234_5678,
+            data: (0..beats as u8).map(|i| vec![i + 1; 4]).collect(),
+            strb: (0..beats as u128).map(|i| 0xf >> (i % 4)).collect(),
+            wUser: (0..beats as u32).map(|i| 0x10 + i).collect(),
+            awUser: 0x42,
+            dataValid: true,
+            burst: 1,
+            cache: 0x2,
+            lock: 0,
+            prot: 0x1,
+            qos: 0x4,
+            region: 0x8,
+            size: 2,
+        }
+    }
+
+    #[test]
+    fn write_payload_round_trip() {
+        let layout = PayloadLayout {
+            write_payload_size: 8,
+            ..test_layout()
+        };
+        for beats in [1, 3, 8] {
+            let payload = sample_write_payload(beats);
+            let mut buf = vec![0; sv_words(layout.write_payload_width())];
+            payload.pack(&layout, &mut buf);
+            assert_eq!(AxiWritePayload::unpack(&layout, &buf), payload);
+        }
+    }
+
+    #[test]
+    fn write_payload_bit_layout() {
+        let layout = test_layout();
+        // 8 + 8 + 32 + (32 + 8 + 8) + 8 + 1 + 7 * 8
+        assert_eq!(layout.write_payload_width(), 161);
+
+        let payload = sample_write_payload(1);
+        let mut buf = vec![0; 6];
+        payload.pack(&layout, &mut buf);
+        // size is the least significant byte, dataValid sits above the 7 control bytes
+        assert_eq!(buf[0] & 0xff, 2);
+        assert_eq!((buf[1] >> 24) & 1, 1);
+        // data occupies [112:81]
+        let data = (buf[2] >> 17) as u64 | (buf[3] as u64 & 0x1ffff) << 15;
+        assert_eq!(data, 0x0101_0101);
+        // id is the most significant byte: [160:153]
+        assert_eq!(buf[4] >> 25 | (buf[5] & 1) << 7, 0x3);
+    }
+
+    #[test]
+    #[should_panic(expected = "does not fit")]
+    fn write_payload_too_long() {
+        let layout = test_layout();
+        let payload = sample_write_payload(layout.write_payload_size + 1);
+        let mut buf = vec![0; sv_words(layout.write_payload_width())];
+        payload.pack(&layout, &mut buf);
+    }
+
+    #[test]
+    fn write_payload_wide_bus() {
+        for data_width in [64, 128] {
+            let layout = PayloadLayout {
+                data_width,
+                write_payload_size: 4,
+                ..test_layout()
+            };
+            let bus_bytes = data_width as usize / 8;
+            let payload = AxiWritePayload {
+                data: (0..4u8)
+                    .map(|i| (0..bus_bytes as u8).map(|b| b ^ (i << 4)).collect())
+                    .collect(),
+                strb: vec![u128::MAX >> (128 - bus_bytes), 0x1, 0xf0, 0],
+                size: bus_bytes.ilog2() as u8,
+                ..sample_write_payload(4)
+            };
+            let mut buf = vec![0; sv_words(layout.write_payload_width())];
+            payload.pack(&layout, &mut buf);
+            assert_eq!(AxiWritePayload::unpack(&layout, &buf), payload);
+        }
+    }
+
+    #[test]
+    fn random_write_honours_constraints() {
+        use common::constraints::{BurstWeights, InclusiveRange};
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let layout = PayloadLayout {
+            write_payload_size: 16,
+            ..test_layout()
+        };
+        let constraints = Constraints {
+            burst_weights: BurstWeights {
+                fixed: 0,
+                incr: 1,
+                wrap: 1,
+            },
+            beats: InclusiveRange { min: 4, max: 8 },
+            size: InclusiveRange { min: 0, max: 1 },
+            address_windows: vec![
+                AddressWindow {
+                    start: 0x1000,
+                    end: 0x1040,
+                },
+                AddressWindow {
+                    start: 0x8002,
+                    end: 0x8100,
+                },
+            ],
+            strobe_density: 0.5,
+            unaligned: 0.5,
+            ..Constraints::default()
+        };
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..1000 {
+            let payload = AxiWritePayload::random(&layout, &constraints, &mut rng, 0);
+            let beats = payload.len as u64 + 1;
+            let bytes = 1u64 << payload.size;
+            assert!((1..=2).contains(&payload.burst));
+            assert!((4..=8).contains(&beats));
+            assert!(payload.size <= 1);
+            if payload.burst == 2 {
+                assert!(beats == 4 || beats == 8);
+                assert_eq!(payload.addr as u64 % bytes, 0);
+            }
+            let start = if payload.burst == 2 {
+                payload.addr as u64 / (bytes * beats) * bytes * beats
+            } else {
+                payload.addr as u64 / bytes * bytes
+            };
+            let end = start + bytes * beats;
+            assert!(
+                constraints
+                    .address_windows
+                    .iter()
+                    .any(|window| window.start <= start && end <= window.end),
+                "{payload:x?}"
+            );
+            let lanes = burst_lanes(payload.addr, payload.burst, payload.len, payload.size, 4);
+            for (strb, beat) in payload.strb.iter().zip(&lanes) {
+                assert_eq!(strb & !beat.strobe(), 0, "{payload:x?}");
+            }
+        }
+    }
+
+    #[test]
+    fn narrow_lanes_follow_the_address() {
+        let lanes = |addr, burst, len, size| -> Vec<(u32, Range<u32>)> {
+            burst_lanes(addr, burst, len, size, 4)
+                .into_iter()
+                .map(|beat| (beat.base, beat.lanes))
+                .collect()
+        };
+        assert_eq!(
+            lanes(0x102, 1, 2, 1),
+            vec![(0x100, 2..4), (0x104, 0..2), (0x104, 2..4)]
+        );
+        assert_eq!(lanes(0x101, 0, 1, 0), vec![(0x100, 1..2), (0x100, 1..2)]);
+        assert_eq!(
+            lanes(0x10c, 2, 3, 2),
+            vec![(0x10c, 0..4), (0x100, 0..4), (0x104, 0..4), (0x108, 0..4)]
+        );
+        assert_eq!(burst_lanes(0x106, 2, 1, 1, 4)[1].strobe(), 0b0011);
+        // unaligned starts cut the first beat short
+        assert_eq!(
+            lanes(0x101, 1, 2, 2),
+            vec![(0x100, 1..4), (0x104, 0..4), (0x108, 0..4)]
+        );
+        assert_eq!(lanes(0x103, 1, 1, 1), vec![(0x100, 3..4), (0x104, 0..2)]);
+        assert_eq!(lanes(0x105, 0, 1, 1), vec![(0x104, 1..2), (0x104, 1..2)]);
+    }
+}
@@ -1,83 +1,111 @@
-use common::MEM_SIZE;
-use svdpi::{get_time, SvScope};
-use tracing::{error, info, trace};
+use svdpi::get_time;
+use tracing::{error, info, info_span, trace, Span};
 
+use crate::check::{Channel, CheckError, CheckReport, ErrorWindows, Resp};
 use crate::dpi::*;
-use crate::{OfflineArgs, AXI_SIZE};
-use std::collections::VecDeque;
+use crate::protocol::{self, IllegalResponse, Violation};
+use crate::replay::{self, Recorder, ReplayGenerator};
+use crate::scoreboard::Scoreboard;
+use crate::script::{self, Command, ScriptGenerator};
+use crate::traffic::{self, InFlight, TrafficContext, TrafficGenerator};
+use crate::OfflineArgs;
+use common::constraints::Constraints;
+use common::memory_map::MemoryMap;
+use common::rtl_config::RTLConfig;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
-struct ShadowMem {
-    mem: Vec<u8>,
+const PAGE_SHIFT: u32 = 12;
+const PAGE_SIZE: usize = 1 << PAGE_SHIFT;
+
+pub(crate) const WATCHDOG_CONTINUE: u8 = 0;
+pub(crate) const WATCHDOG_TIMEOUT: u8 = 1;
+pub(crate) const WATCHDOG_FINISH: u8 = 2;
+pub(crate) const WATCHDOG_FAIL: u8 = 3;
+
+/// Sparse byte-addressable memory, backed by 4 KiB pages allocated on first write.
+pub(crate) struct ShadowMem {
+    pages: HashMap<u32, Box<[u8; PAGE_SIZE]>>,
+    // value returned when reading a byte that was never written
+    fill: u8,
+    // AXI bus width in bytes
+    bus_bytes: u32,
+    // folds aliased addresses onto the chips
+    pub(crate) map: MemoryMap,
 }
 
+/// The shadow memory every agent writes to and checks against, as they all
+/// reach the same SDRAM chips.
+pub(crate) type SharedMem = Arc<Mutex<ShadowMem>>;
+
 impl ShadowMem {
-    pub fn new() -> Self {
+    pub fn new(fill: u8, bus_bytes: u32, map: MemoryMap) -> Self {
         Self {
-            mem: vec![0; MEM_SIZE],
+            pages: HashMap::new(),
+            fill,
+            bus_bytes,
+            map,
         }
     }
 
-    fn is_addr_align(&self, addr: u32, size: u8) -> bool {
-        let bytes_number = 1 << size;
-        let aligned_addr = addr / bytes_number * bytes_number;
-        addr == aligned_addr
+    fn read_byte(&self, addr: u32) -> u8 {
+        let Some(addr) = self.map.translate(addr as u64) else {
+            return self.fill;
+        };
+        let addr = addr as u32;
+        match self.pages.get(&(addr >> PAGE_SHIFT)) {
+            Some(page) => page[addr as usize & (PAGE_SIZE - 1)],
+            None => self.fill,
+        }
     }
 
-    // size: 1 << arsize
-    // return: Vec<u8> with len=bus_size
-    pub fn read_mem_axi(&self, payload: AxiReadPayload) -> Vec<u8> {
-        let bytes_number: u32 = 1 << payload.size;
-
-        let transfer_count: u32 = (payload.len + 1) as u32;
-
-        let mut lower_boundary = 0;
-        let mut upper_boundary = 0;
-
-        let mut data: Vec<u8> = vec![];
+    fn write_byte(&mut self, addr: u32, value: u8) {
+        // out-of-range writes are dropped by the controller
+        let Some(addr) = self.map.translate(addr as u64) else {
+            return;
+        };
+        let addr = addr as u32;
+        let fill = self.fill;
+        let page = self
+            .pages
+            .entry(addr >> PAGE_SHIFT)
+            .or_insert_with(|| Box::new([fill; PAGE_SIZE]));
+        page[addr as usize & (PAGE_SIZE - 1)] = value;
+    }
 
-        if payload.burst == 2 {
-            lower_boundary =
-                payload.addr / (bytes_number * transfer_count) * (bytes_number * transfer_count);
-            upper_boundary = lower_boundary + bytes_number * transfer_count;
-            assert!(
-                payload.len == 2 || payload.len == 4 || payload.len == 8 || payload.len == 16,
-                "unsupported burst len"
-            );
-        }
+    fn read_bytes(&self, addr: u32, len: u32) -> Vec<u8> {
+        (0..len)
+            .map(|offset| self.read_byte(addr.wrapping_add(offset)))
+            .collect()
+    }
 
-        let mut current_addr = payload.addr;
-        assert!(
-            self.is_addr_align(payload.addr, payload.size),
-            "address is unaligned!"
-        );
+    pub fn allocated_pages(&self) -> usize {
+        self.pages.len()
+    }
 
-        for _ in 0..transfer_count {
-            data.extend_from_slice(
-                &self.mem[current_addr as usize..(current_addr + bytes_number) as usize],
-            );
+    pub fn allocated_bytes(&self) -> usize {
+        self.pages.len() * PAGE_SIZE
+    }
 
-            current_addr = match payload.burst {
-                0 => current_addr,                // FIXED
-                1 => current_addr + bytes_number, // INCR
-                2 => {
-                    if current_addr + bytes_number >= upper_boundary {
-                        lower_boundary
-                    } else {
-                        current_addr + bytes_number
-                    }
-                } // WRAP
-                _ => {
-                    panic!("unknown burst type: {:?}", payload.burst);
-                }
-            }
-        }
-        data
+    /// Bytes of the active lanes of every beat, in beat order.
+    pub fn read_mem_axi(&self, payload: &AxiReadPayload) -> Vec<u8> {
+        burst_lanes(
+            payload.addr,
+            payload.burst,
+            payload.len,
+            payload.size,
+            self.bus_bytes,
+        )
+        .into_iter()
+        .flat_map(|beat| self.read_bytes(beat.base + beat.lanes.start, beat.lanes.len() as u32))
+        .collect()
     }
 
-    // size: 1 << awsize
-    // bus_size: AXI bus width in bytes
-    // masks: write strokes, len=bus_size
-    // data: write data, len=bus_size
+    /// Store the strobed lanes of every beat, any subset of the active lanes
+    /// may be strobed.
     pub fn write_mem_axi(&mut self, payload: AxiWritePayload) {
         let transfer_count = (payload.len + 1) as usize;
 
@@ -87,127 +115,168 @@ impl ShadowMem {
             "malformed payload: transfer_count = {:?}, payload.data.len = {:?}, payload.strb.len = {:?}",
             transfer_count, payload.data.len(), payload.strb.len(),
         );
+        let lanes = burst_lanes(
+            payload.addr,
+            payload.burst,
+            payload.len,
+            payload.size,
+            self.bus_bytes,
+        );
+        for (item_idx, beat) in lanes.into_iter().enumerate() {
+            // strobes outside the active lanes are ignored
+            for lane in beat.lanes {
+                if (payload.strb[item_idx] >> lane) & 1 != 0 {
+                    self.write_byte(beat.base + lane, payload.data[item_idx][lane as usize]);
+                }
+            }
+        }
+    }
+}
 
-        let bytes_number: u32 = 1 << payload.size;
-
-        let mut lower_boundary = 0;
-        let mut upper_boundary = 0;
-        if payload.burst == 2 {
-            lower_boundary = payload.addr / (bytes_number * transfer_count as u32)
-                * (bytes_number * transfer_count as u32);
-            upper_boundary = lower_boundary + bytes_number * transfer_count as u32;
-            assert!(
-                payload.len == 2 || payload.len == 4 || payload.len == 8 || payload.len == 16,
-                "unsupported burst len"
-            );
+/// End-of-test conditions, the run drains and finishes once any of them is met.
+struct EndCondition {
+    max_writes: Option<u64>,
+    max_reads: Option<u64>,
+    max_bytes: Option<u64>,
+    max_ticks: Option<u64>,
+}
+
+impl EndCondition {
+    fn reached(&self, stats: &RunStats, tick: u64) -> bool {
+        let reached = |limit: Option<u64>, value: u64| limit.is_some_and(|limit| value >= limit);
+        reached(self.max_writes, stats.completed_writes)
+            || reached(self.max_reads, stats.completed_reads)
+            || reached(self.max_bytes, stats.verified_bytes)
+            || reached(self.max_ticks, tick)
+    }
+}
+
+/// Tick of the last sign of life of the DUT, watched for the timeout.
+///
+/// Only B and R responses count: the agent keeps issuing to a DUT that accepts
+/// requests but never answers them. A request only counts when nothing was
+/// outstanding, so an idle agent does not time out.
+#[derive(Default)]
+struct Progress {
+    last_tick: u64,
+}
+
+impl Progress {
+    /// A request was issued at `tick` with `outstanding` transactions in flight.
+    fn issued(&mut self, tick: u64, outstanding: usize) {
+        if outstanding == 0 {
+            self.last_tick = tick;
         }
+    }
 
-        let mut current_addr = payload.addr;
-        assert!(
-            self.is_addr_align(payload.addr, payload.size),
-            "address is unaligned!"
-        );
+    fn responded(&mut self, tick: u64) {
+        self.last_tick = tick;
+    }
 
-        for item_idx in 0..transfer_count {
-            if payload.strb[item_idx] == 0 {
-                continue;
-            }
+    fn timed_out(&self, tick: u64, timeout: u64) -> bool {
+        tick.saturating_sub(self.last_tick) > timeout
+    }
+}
 
-            assert_eq!(
-                payload.strb[item_idx].count_ones(),
-                bytes_number,
-                "the number of will write bytes is not equal"
-            );
+/// A read in flight, with the data it has to return.
+///
+/// The data is taken from the shadow memory when the AR is issued: a write
+/// issued after it may reach the chips before or after the read is served.
+struct PendingRead {
+    payload: AxiReadPayload,
+    expected: Vec<u8>,
+}
 
-            let mut write_count = 0;
+impl PendingRead {
+    fn new(payload: AxiReadPayload, shadow_mem: &ShadowMem) -> Self {
+        let expected = shadow_mem.read_mem_axi(&payload);
+        Self { payload, expected }
+    }
+}
 
-            for byte_idx in 0..AXI_SIZE / 8 {
-                let byte_mask: bool = (payload.strb[item_idx] >> byte_idx) & 1 != 0;
-                if byte_mask {
-                    self.mem[(current_addr + write_count) as usize] =
-                        (payload.data[byte_idx as usize] >> (byte_idx * 8) & 0xff) as u8;
-                    write_count += 1;
-                }
-            }
+#[derive(Default, Debug)]
+struct RunStats {
+    completed_writes: u64,
+    completed_reads: u64,
+    verified_bytes: u64,
+}
 
-            current_addr = match payload.burst {
-                0 => current_addr,                // FIXED
-                1 => current_addr + bytes_number, // INCR
-                2 => {
-                    if current_addr + bytes_number >= upper_boundary {
-                        lower_boundary
-                    } else {
-                        current_addr + bytes_number
-                    }
-                } // WRAP
-                _ => {
-                    panic!("unknown burst type: {:?}", payload.burst);
-                }
-            }
-        }
+/// Transactions in flight, for the generator to keep clear of.
+fn in_flight<'a>(
+    memory_map: &'a MemoryMap,
+    layout: &PayloadLayout,
+    writes: &Scoreboard<AxiWritePayload>,
+    reads: &Scoreboard<PendingRead>,
+) -> InFlight<'a> {
+    let mut in_flight = InFlight::new(memory_map, layout.data_width / 8);
+    for (_, writes) in writes.outstanding_by_id() {
+        writes
+            .iter()
+            .for_each(|payload| in_flight.add_write(payload));
     }
+    for (_, reads) in reads.outstanding_by_id() {
+        reads
+            .iter()
+            .for_each(|read| in_flight.add_read(&read.payload));
+    }
+    in_flight
 }
 
+/// One `AXI4MasterAgent` of the testbench, with its own stimulus and scoreboards.
 pub(crate) struct Driver {
-    // SvScope from cosim_init
-    scope: SvScope,
+    // `channelId` of the agent
+    channel_id: u64,
 
-    #[cfg(feature = "trace")]
-    wave_path: String,
-    #[cfg(feature = "trace")]
-    dump_start: u64,
-    #[cfg(feature = "trace")]
-    dump_end: u64,
-    #[cfg(feature = "trace")]
-    dump_started: bool,
+    // logs of this agent are emitted within it
+    pub(crate) span: Span,
 
-    pub(crate) dlen: u32,
+    pub(crate) layout: PayloadLayout,
 
     timeout: u64,
 
     clock_flip_time: u64,
 
-    shadow_mem: ShadowMem,
+    // tick of the last B/R response seen through DPI
+    progress: Progress,
 
-    axi_write_done_fifo: VecDeque<AxiWritePayload>,
+    shadow_mem: SharedMem,
 
-    axi_write_fifo: VecDeque<AxiWritePayload>,
+    memory_map: MemoryMap,
 
-    axi_read_fifo: VecDeque<AxiReadPayload>,
+    axi_write_scoreboard: Scoreboard<AxiWritePayload>,
 
-    axi_read_buffer: Vec<u8>,
-}
+    axi_read_scoreboard: Scoreboard<PendingRead>,
 
-#[cfg(feature = "trace")]
-fn parse_range(input: &str) -> (u64, u64) {
-    if input.is_empty() {
-        return (0, 0);
-    }
+    // bus-wide R beats and their RRESP of the bursts in flight, by RID
+    axi_read_buffer: HashMap<u8, Vec<(Vec<u8>, Resp)>>,
 
-    let parts: Vec<&str> = input.split(",").collect();
+    error_windows: ErrorWindows,
 
-    if parts.len() != 1 && parts.len() != 2 {
-        error!("invalid dump wave range: `{input}` was given");
-        return (0, 0);
-    }
+    checks: CheckReport,
 
-    const INVALID_NUMBER: &'static str = "invalid number";
+    end_condition: EndCondition,
 
-    if parts.len() == 1 {
-        return (parts[0].parse().expect(INVALID_NUMBER), 0);
-    }
+    stats: RunStats,
 
-    if parts[0].is_empty() {
-        return (0, parts[1].parse().expect(INVALID_NUMBER));
-    }
+    // no new transactions are issued once set
+    draining: bool,
 
-    let start = parts[0].parse().expect(INVALID_NUMBER);
-    let end = parts[1].parse().expect(INVALID_NUMBER);
-    if start > end {
-        panic!("dump start is larger than end: `{input}`");
-    }
+    // verdict of the agent once it drained or timed out
+    verdict: Option<u8>,
+
+    seed: u64,
+
+    // the only source of randomness, so a run replays bit-for-bit from its seed
+    rng: StdRng,
+
+    generator: Box<dyn TrafficGenerator>,
+
+    recorder: Option<Recorder>,
+
+    // share of the bursts turned illegal, illegal bursts are dropped when 0
+    illegal_traffic: f64,
 
-    (start, end)
+    illegal_response: IllegalResponse,
 }
 
 impl Driver {
@@ -215,105 +284,741 @@ impl Driver {
         get_time() / self.clock_flip_time
     }
 
-    pub(crate) fn new(scope: SvScope, args: &OfflineArgs) -> Self {
-        #[cfg(feature = "trace")]
-        let (dump_start, dump_end) = parse_range(&args.dump_range);
+    /// `file` maps a path given on the command line (`--script`, `--replay`,
+    /// `--record`, `--constraints`) to the file of this agent: the path
+    /// itself with one agent, `<stem>.<agent>.<ext>` with several.
+    pub(crate) fn new(
+        name: &str,
+        channel_id: u64,
+        args: &OfflineArgs,
+        config: &RTLConfig,
+        shadow_mem: SharedMem,
+        seed: u64,
+        file: impl Fn(&Path) -> PathBuf,
+    ) -> Self {
+        let layout = PayloadLayout::new(config.axi());
+        let memory_map = shadow_mem.lock().unwrap().map.clone();
+        let addr_space = 1u64 << layout.addr_width.min(32);
+        let mut error_windows = args.expect_error.clone();
+        if !memory_map.alias {
+            // unmapped addresses have to be answered with an error
+            error_windows.extend(
+                memory_map
+                    .holes(addr_space)
+                    .iter()
+                    .map(|hole| hole.start..hole.end),
+            );
+        }
+        let generator: Box<dyn TrafficGenerator> = match (&args.script, &args.replay) {
+            (_, Some(path)) => {
+                let path = &file(path);
+                let content = std::fs::read_to_string(path)
+                    .unwrap_or_else(|e| panic!("failed to read trace {}: {e}", path.display()));
+                let transactions = replay::parse(&content, &layout)
+                    .unwrap_or_else(|e| panic!("invalid trace {}: {e}", path.display()));
+                info!(
+                    "replaying {} transaction(s) from {}",
+                    transactions.len(),
+                    path.display()
+                );
+                Box::new(ReplayGenerator::new(transactions))
+            }
+            (Some(path), None) => {
+                let path = &file(path);
+                let content = std::fs::read_to_string(path)
+                    .unwrap_or_else(|e| panic!("failed to read script {}: {e}", path.display()));
+                let commands = script::parse(&content, &layout)
+                    .unwrap_or_else(|e| panic!("invalid script {}: {e}", path.display()));
+                info!(
+                    "running {} command(s) from {}",
+                    commands.len(),
+                    path.display()
+                );
+                Box::new(ScriptGenerator::new(commands))
+            }
+            (None, None) => {
+                let mut constraints = match &args.constraints {
+                    Some(path) => Constraints::load(file(path)).unwrap(),
+                    None => Constraints::default(),
+                };
+                constraints.apply_memory_map(&memory_map, addr_space);
+                traffic::by_name(&args.traffic, constraints).unwrap()
+            }
+        };
+        let recorder = args.record.as_ref().map(|path| {
+            let path = &file(path);
+            Recorder::create(path, seed)
+                .unwrap_or_else(|e| panic!("failed to create trace {}: {e}", path.display()))
+        });
 
         let self_ = Self {
-            scope,
-
-            #[cfg(feature = "trace")]
-            wave_path: args.wave_path.to_owned(),
-            #[cfg(feature = "trace")]
-            dump_start,
-            #[cfg(feature = "trace")]
-            dump_end,
-            #[cfg(feature = "trace")]
-            dump_started: false,
-            dlen: args.common_args.dlen,
-            timeout: args.timeout,
-            clock_flip_time: args.clock_flip_time,
-            shadow_mem: ShadowMem::new(),
-            axi_read_fifo: VecDeque::new(),
-            axi_write_done_fifo: VecDeque::new(),
-            axi_write_fifo: VecDeque::new(),
-            axi_read_buffer: Vec::new(),
+            channel_id,
+            span: info_span!("agent", name),
+            layout,
+            timeout: config.timeout,
+            clock_flip_time: config.test_verbatim_parameter.clock_flip_tick * args.timescale,
+            progress: Progress::default(),
+            shadow_mem,
+            memory_map,
+            axi_read_scoreboard: Scoreboard::new("read"),
+            axi_write_scoreboard: Scoreboard::new("write"),
+            axi_read_buffer: HashMap::new(),
+            error_windows: ErrorWindows::new(error_windows),
+            checks: CheckReport::default(),
+            end_condition: EndCondition {
+                max_writes: args.max_writes,
+                max_reads: args.max_reads,
+                max_bytes: args.max_bytes,
+                max_ticks: args.max_ticks,
+            },
+            stats: RunStats::default(),
+            draining: false,
+            verdict: None,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            generator,
+            recorder,
+            illegal_traffic: args.illegal_traffic,
+            illegal_response: args.illegal_response,
         };
 
         self_
     }
 
-    pub(crate) fn init(&mut self) {
-        #[cfg(feature = "trace")]
-        if self.dump_start == 0 {
-            self.start_dump_wave();
-            self.dump_started = true;
-        }
+    pub(crate) fn init(&self) {
+        info!("channel {}, seed {}", self.channel_id, self.seed);
+    }
+
+    /// Panic unless the RTL instance has the `channelId` of this agent, which
+    /// numbers the agents in the order of `--agent`.
+    pub(crate) fn check_channel_id(&self, channel_id: u64) {
+        assert_eq!(
+            channel_id, self.channel_id,
+            "the RTL agent has channelId {channel_id}, give --agent in channelId order"
+        );
     }
 
+    /// `WATCHDOG_CONTINUE` until the agent drained or timed out, its verdict after.
     pub(crate) fn watchdog(&mut self) -> u8 {
-        const WATCHDOG_CONTINUE: u8 = 0;
-        const WATCHDOG_TIMEOUT: u8 = 1;
-        const WATCHDOG_FINISH: u8 = 2;
+        if let Some(verdict) = self.verdict {
+            return verdict;
+        }
 
         let tick = self.get_tick();
-        #[cfg(feature = "trace")]
-        if self.dump_end != 0 && tick > self.dump_end {
-            info!("[{tick}] run to dump end, exiting");
-            return WATCHDOG_FINISH;
+        if self.update_draining(tick) && self.outstanding() == 0 {
+            info!("[{tick}] end of test reached and all transactions drained");
+            let verdict = if self.checks.passed() {
+                WATCHDOG_FINISH
+            } else {
+                WATCHDOG_FAIL
+            };
+            self.verdict = Some(verdict);
+            return verdict;
         }
 
-        #[cfg(feature = "trace")]
-        if !self.dump_started && tick >= self.dump_start {
-            self.start_dump_wave();
-            self.dump_started = true;
+        if self.progress.timed_out(tick, self.timeout) {
+            error!(
+                "[{tick}] no AXI response since tick {}, timeout",
+                self.progress.last_tick
+            );
+            self.dump_outstanding();
+            self.verdict = Some(WATCHDOG_TIMEOUT);
+            return WATCHDOG_TIMEOUT;
         }
-        trace!("[{tick}] watchdog continue");
+
         WATCHDOG_CONTINUE
     }
 
-    pub(crate) fn axi_read_resp(&mut self, rdata: u32, rid: u8, rlast: u8, rresp: u8, ruser: u8) {
+    pub(crate) fn passed(&self) -> bool {
+        self.checks.passed()
+    }
+
+    /// Summarize the run, called once before the simulation stops.
+    pub(crate) fn report(&self) {
+        info!(
+            "{} write burst(s) and {} read burst(s) completed, {} byte(s) verified",
+            self.stats.completed_writes, self.stats.completed_reads, self.stats.verified_bytes
+        );
+        self.axi_write_scoreboard.report();
+        self.axi_read_scoreboard.report();
+        self.checks.report();
+    }
+
+    fn dump_outstanding(&self) {
+        for (id, writes) in self.axi_write_scoreboard.outstanding_by_id() {
+            for payload in writes {
+                error!(
+                    "  outstanding write id {id}: addr={:#010x} len={} size={} burst={}",
+                    payload.addr, payload.len, payload.size, payload.burst
+                );
+            }
+        }
+        for (id, reads) in self.axi_read_scoreboard.outstanding_by_id() {
+            let beats = self.axi_read_buffer.get(&id).map_or(0, Vec::len);
+            for (index, PendingRead { payload, .. }) in reads.iter().enumerate() {
+                error!(
+                    "  outstanding read id {id}: addr={:#010x} len={} size={} burst={}{}",
+                    payload.addr,
+                    payload.len,
+                    payload.size,
+                    payload.burst,
+                    if index == 0 && beats > 0 {
+                        format!(", {beats} beat(s) received")
+                    } else {
+                        String::new()
+                    }
+                );
+            }
+        }
+    }
+
+    /// Whether the end of test is reached and no new transaction may be issued.
+    fn update_draining(&mut self, tick: u64) -> bool {
+        if !self.draining
+            && (self.end_condition.reached(&self.stats, tick) || self.generator.finished())
+        {
+            info!("[{tick}] end of test reached ({:?}), draining", self.stats);
+            self.draining = true;
+        }
+        self.draining
+    }
+
+    fn outstanding(&self) -> usize {
+        self.axi_write_scoreboard.outstanding() + self.axi_read_scoreboard.outstanding()
+    }
+
+    fn expects_error(&self, addr: u32, len: u8, size: u8) -> bool {
+        self.error_windows
+            .expects_error(addr, (len as u32 + 1) << size)
+    }
+
+    // illegal bursts are only issued with --illegal-traffic, and otherwise
+    // carried out like legal ones
+    fn write_expects_error(&self, payload: &AxiWritePayload) -> bool {
+        self.expects_error(payload.addr, payload.len, payload.size)
+            || self.illegal_response == IllegalResponse::Error
+                && !protocol::check_write(payload, &self.layout).is_empty()
+    }
+
+    fn read_expects_error(&self, payload: &AxiReadPayload) -> bool {
+        self.expects_error(payload.addr, payload.len, payload.size)
+            || self.illegal_response == IllegalResponse::Error
+                && !protocol::check_read(payload, &self.layout).is_empty()
+    }
+
+    /// Report the AXI4 rules a request breaks, returns whether it may be issued.
+    fn admit(&mut self, channel: Channel, id: u8, addr: u32, violations: Vec<Violation>) -> bool {
+        if violations.is_empty() {
+            return true;
+        }
+        let tick = self.get_tick();
+        if self.illegal_traffic > 0.0 {
+            for violation in violations {
+                info!(
+                    "[{tick}] {channel:?}: id {id} addr {addr:#010x} issued illegal, {violation}"
+                );
+            }
+            return true;
+        }
+        for violation in violations {
+            self.checks.record(
+                tick,
+                CheckError::Protocol {
+                    channel,
+                    id,
+                    addr,
+                    violation,
+                },
+            );
+        }
+        false
+    }
+
+    pub(crate) fn axi_read_resp(&mut self, rdata: &[u8], rid: u8, rlast: u8, rresp: u8, ruser: u8) {
         trace!(
-            "axi_read_resp (rdata={rdata}, rid={rid}, rlast={rlast:#x}, \
-    rresp={rresp}, ruser={ruser})"
+            "axi_read_resp (rdata={}, rid={rid}, rlast={rlast:#x}, \
+    rresp={rresp}, ruser={ruser})",
+            hex::encode(rdata)
         );
-        self.axi_read_buffer.extend_from_slice(&rdata.to_le_bytes());
-        if rlast == 1 {
-            let payload = self.axi_read_fifo.pop_front().unwrap();
-            let compare = self.shadow_mem.read_mem_axi(payload);
-            assert_eq!(
-                compare, self.axi_read_buffer,
-                "compare failed: {:?} -> {:?}",
-                self.axi_read_buffer, compare
+        let tick = self.get_tick();
+        self.progress.responded(tick);
+        let Some(oldest) = self.axi_read_scoreboard.oldest(rid) else {
+            // once per burst rather than per beat
+            if rlast == 1 {
+                self.checks.record(
+                    tick,
+                    CheckError::UnknownId {
+                        channel: Channel::R,
+                        id: rid,
+                    },
+                );
+            }
+            return;
+        };
+        let (addr, expected_beats) = (oldest.payload.addr, oldest.payload.len as usize + 1);
+
+        let beats = self.axi_read_buffer.entry(rid).or_default();
+        beats.push((rdata.to_vec(), Resp::from_bits(rresp)));
+        let received_beats = beats.len();
+        if rlast == 1 && received_beats < expected_beats {
+            self.checks.record(
+                tick,
+                CheckError::EarlyLast {
+                    id: rid,
+                    addr,
+                    expected: expected_beats,
+                    received: received_beats,
+                },
+            );
+        } else if rlast != 1 && received_beats == expected_beats {
+            self.checks.record(
+                tick,
+                CheckError::MissingLast {
+                    id: rid,
+                    addr,
+                    expected: expected_beats,
+                },
+            );
+        } else if rlast != 1 {
+            return;
+        }
+
+        let beats = self.axi_read_buffer.remove(&rid).unwrap();
+        let bus_bytes = self.layout.data_width / 8;
+        // only the active byte lanes of each beat carry data
+        let received = |payload: &AxiReadPayload| -> Vec<u8> {
+            let lanes = burst_lanes(
+                payload.addr,
+                payload.burst,
+                payload.len,
+                payload.size,
+                bus_bytes,
+            );
+            beats
+                .iter()
+                .zip(lanes)
+                .flat_map(|((beat, _), lanes)| {
+                    beat[lanes.lanes.start as usize..lanes.lanes.end as usize]
+                        .iter()
+                        .copied()
+                })
+                .collect()
+        };
+        // same-ID reads complete in order, a corrupt burst is still the oldest's,
+        // but data of a younger one tells the DUT reordered them
+        let PendingRead { payload, expected } = self
+            .axi_read_scoreboard
+            .complete(rid, |read| read.expected == received(&read.payload))
+            .unwrap_or_else(|| unreachable!("id {rid} was outstanding"));
+        self.stats.completed_reads += 1;
+        self.generator.read_done(&payload);
+        let script_expected = self.generator.expected_read(&payload);
+
+        if let Some(violation) = beats
+            .iter()
+            .find_map(|(_, resp)| protocol::check_response(*resp, payload.lock))
+        {
+            self.checks.record(
+                tick,
+                CheckError::Protocol {
+                    channel: Channel::R,
+                    id: rid,
+                    addr: payload.addr,
+                    violation,
+                },
+            );
+        }
+        let expect_error = self.read_expects_error(&payload);
+        if let Some((_, resp)) = beats
+            .iter()
+            .find(|(_, resp)| resp.is_error() != expect_error)
+        {
+            self.checks.record(
+                tick,
+                CheckError::Response {
+                    channel: Channel::R,
+                    id: rid,
+                    addr: payload.addr,
+                    expect_error,
+                    received: *resp,
+                },
+            );
+        }
+        // data is meaningless on an error response
+        if expect_error {
+            return;
+        }
+        let received = received(&payload);
+        if let Some(expected) = script_expected.filter(|expected| *expected != received) {
+            self.checks.record(
+                tick,
+                CheckError::ReadData {
+                    id: rid,
+                    addr: payload.addr,
+                    expected,
+                    received: received.clone(),
+                },
+            );
+        }
+        if expected == received {
+            self.stats.verified_bytes += received.len() as u64;
+        } else {
+            self.checks.record(
+                tick,
+                CheckError::ReadData {
+                    id: rid,
+                    addr: payload.addr,
+                    expected,
+                    received,
+                },
             );
-            self.axi_read_buffer.clear();
         }
     }
 
     pub(crate) fn axi_write_done(&mut self, bid: u8, bresp: u8, buser: u8) {
         trace!("axi_write_done (bid={bid}, bresp={bresp}, buser={buser})");
-        let payload = self.axi_write_fifo.pop_front().unwrap();
-        self.axi_write_done_fifo.push_back(payload);
+        let tick = self.get_tick();
+        self.progress.responded(tick);
+        // B carries no data, so the oldest write of this ID is the only candidate
+        let Some(payload) = self.axi_write_scoreboard.complete(bid, |_| true) else {
+            self.checks.record(
+                tick,
+                CheckError::UnknownId {
+                    channel: Channel::B,
+                    id: bid,
+                },
+            );
+            return;
+        };
+        self.stats.completed_writes += 1;
+
+        let received = Resp::from_bits(bresp);
+        if let Some(violation) = protocol::check_response(received, payload.lock) {
+            self.checks.record(
+                tick,
+                CheckError::Protocol {
+                    channel: Channel::B,
+                    id: bid,
+                    addr: payload.addr,
+                    violation,
+                },
+            );
+        }
+        let expect_error = self.write_expects_error(&payload);
+        if received.is_error() != expect_error {
+            self.checks.record(
+                tick,
+                CheckError::Response {
+                    channel: Channel::B,
+                    id: bid,
+                    addr: payload.addr,
+                    expect_error,
+                    received,
+                },
+            );
+        }
+        // only report writes that reached memory
+        if !expect_error {
+            self.generator.write_done(&payload);
+        }
     }
 
-    pub(crate) fn axi_write_ready(&mut self) -> AxiWritePayload {
+    pub(crate) fn axi_write_ready(&mut self) -> Option<AxiWritePayload> {
         trace!("axi_write_ready");
-        let payload = AxiWritePayload::random();
-        self.axi_write_fifo.push_back(payload.clone());
-        self.shadow_mem.write_mem_axi(payload.clone());
-        payload
+        let tick = self.get_tick();
+        if self.update_draining(tick) {
+            return None;
+        }
+        let outstanding = self.outstanding();
+        let in_flight = in_flight(
+            &self.memory_map,
+            &self.layout,
+            &self.axi_write_scoreboard,
+            &self.axi_read_scoreboard,
+        );
+        let mut payload = self.generator.next_write(&mut TrafficContext {
+            layout: &self.layout,
+            rng: &mut self.rng,
+            tick,
+            outstanding,
+            in_flight: &in_flight,
+        })?;
+        if self.illegal_traffic > 0.0 && self.rng.gen_bool(self.illegal_traffic) {
+            let mut illegal = payload.clone();
+            protocol::make_illegal_write(&mut illegal, &self.layout, &mut self.rng);
+            // the broken burst may reach bytes the generator kept clear of
+            if !in_flight.blocks_write(&illegal) {
+                payload = illegal;
+            }
+        }
+        let violations = protocol::check_write(&payload, &self.layout);
+        if !self.admit(Channel::AW, payload.id, payload.addr, violations) {
+            return None;
+        }
+        self.progress.issued(tick, outstanding);
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(tick, &Command::Write(payload.clone()));
+        }
+        self.axi_write_scoreboard.issue(payload.id, payload.clone());
+        if !self.write_expects_error(&payload) {
+            self.shadow_mem
+                .lock()
+                .unwrap()
+                .write_mem_axi(payload.clone());
+        }
+        Some(payload)
     }
 
-    pub(crate) fn axi_read_ready(&mut self) -> AxiReadPayload {
+    pub(crate) fn axi_read_ready(&mut self) -> Option<AxiReadPayload> {
         trace!("axi_read_ready");
-        let payload =
-            AxiReadPayload::from_write_payload(self.axi_write_done_fifo.pop_front().unwrap());
-        self.axi_read_fifo.push_back(payload.clone());
-        payload
+        let tick = self.get_tick();
+        if self.update_draining(tick) {
+            return None;
+        }
+        let outstanding = self.outstanding();
+        let in_flight = in_flight(
+            &self.memory_map,
+            &self.layout,
+            &self.axi_write_scoreboard,
+            &self.axi_read_scoreboard,
+        );
+        let mut payload = self.generator.next_read(&mut TrafficContext {
+            layout: &self.layout,
+            rng: &mut self.rng,
+            tick,
+            outstanding,
+            in_flight: &in_flight,
+        })?;
+        if self.illegal_traffic > 0.0 && self.rng.gen_bool(self.illegal_traffic) {
+            let mut illegal = payload.clone();
+            protocol::make_illegal_read(&mut illegal, &self.layout, &mut self.rng);
+            // the broken burst may reach bytes the generator kept clear of
+            if !in_flight.blocks_read(&illegal) {
+                payload = illegal;
+            }
+        }
+        let violations = protocol::check_read(&payload, &self.layout);
+        if !self.admit(Channel::AR, payload.id, payload.addr, violations) {
+            return None;
+        }
+        self.progress.issued(tick, outstanding);
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(
+                tick,
+                &Command::Read {
+                    payload: payload.clone(),
+                    expect: None,
+                },
+            );
+        }
+        let pending = PendingRead::new(payload.clone(), &self.shadow_mem.lock().unwrap());
+        self.axi_read_scoreboard.issue(payload.id, pending);
+        Some(payload)
     }
+}
 
-    #[cfg(feature = "trace")]
-    fn start_dump_wave(&mut self) {
-        dump_wave(self.scope, &self.wave_path);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::constraints::AddressWindow;
+
+    #[test]
+    fn shadow_mem_aliases_upper_addresses() {
+        let mut mem = ShadowMem::new(0xee, 4, MemoryMap::default());
+        mem.write_byte(0x0200_0010, 0x5a);
+        assert_eq!(mem.read_byte(0x10), 0x5a);
+        assert_eq!(mem.read_byte(0xfe00_0010), 0x5a);
+
+        let mut strict = ShadowMem::new(
+            0xee,
+            4,
+            MemoryMap {
+                alias: false,
+                ..MemoryMap::default()
+            },
+        );
+        strict.write_byte(0x0200_0010, 0x5a);
+        assert_eq!(strict.read_byte(0x10), 0xee);
+        assert_eq!(strict.allocated_pages(), 0);
+    }
+
+    #[test]
+    fn accepted_illegal_writes_are_modelled() {
+        let layout = PayloadLayout {
+            write_payload_size: 2,
+            ..test_layout()
+        };
+        let script = "
+            write addr=0x40 burst=incr size=2 data=11111111,22222222
+            read addr=0x40 burst=incr size=2 len=1
+        ";
+        let Ok(
+            [Command::Write(mut write), Command::Read {
+                payload: mut read, ..
+            }],
+        ) = <[Command; 2]>::try_from(script::parse(script, &layout).unwrap())
+        else {
+            unreachable!()
+        };
+        let mut mem = ShadowMem::new(0xee, 4, MemoryMap::default());
+        // the controller steps the reserved burst type like INCR
+        write.burst = 0b11;
+        mem.write_mem_axi(write.clone());
+        assert_eq!(mem.read_mem_axi(&read), [[0x11; 4], [0x22; 4]].concat());
+
+        // and a transfer wider than the bus by the bus width
+        write.burst = 1;
+        write.size = 3;
+        write.addr = 0x80;
+        mem.write_mem_axi(write.clone());
+        read.addr = 0x80;
+        assert_eq!(mem.read_mem_axi(&read), [[0x11; 4], [0x22; 4]].concat());
+    }
+
+    #[test]
+    fn hung_b_times_out() {
+        let mut progress = Progress::default();
+        // the first write is issued while idle
+        progress.issued(5, 0);
+        // the DUT keeps taking writes but never answers with B
+        for tick in 6..=105 {
+            progress.issued(tick, (tick - 5) as usize);
+            assert!(!progress.timed_out(tick, 100));
+        }
+        assert!(progress.timed_out(106, 100));
+
+        // a response restarts the timeout
+        progress.responded(106);
+        assert!(!progress.timed_out(206, 100));
+        assert!(progress.timed_out(207, 100));
+    }
+
+    #[test]
+    fn read_expects_data_of_its_issue() {
+        let script = "
+            write addr=0x40 burst=incr size=2 data=11111111,11111111
+            read addr=0x44 burst=incr size=2 len=1
+            write addr=0x40 burst=incr size=2 data=22222222,22222222
+            read addr=0x44 burst=incr size=2 len=1 id=1
+        ";
+        let mut mem = ShadowMem::new(0xee, 4, MemoryMap::default());
+        let mut scoreboard = Scoreboard::new("read");
+        let layout = PayloadLayout {
+            write_payload_size: 2,
+            ..test_layout()
+        };
+        for command in script::parse(script, &layout).unwrap() {
+            match command {
+                Command::Write(payload) => mem.write_mem_axi(payload),
+                // the first read is still outstanding when the second write
+                // is issued, and may be served before it
+                Command::Read { payload, .. } => {
+                    scoreboard.issue(payload.id, PendingRead::new(payload, &mem))
+                }
+                _ => {}
+            }
+        }
+        let expected = |data| [[data; 4], [0xee; 4]].concat();
+        assert_eq!(
+            scoreboard.complete(0, |_| true).unwrap().expected,
+            expected(0x11)
+        );
+        assert_eq!(
+            scoreboard.complete(1, |_| true).unwrap().expected,
+            expected(0x22)
+        );
+    }
+
+    #[test]
+    fn overlapping_traffic_checks_in_any_order() {
+        let layout = PayloadLayout {
+            write_payload_size: 16,
+            ..test_layout()
+        };
+        // a few hundred bytes, so that most bursts overlap another
+        let constraints = Constraints {
+            address_windows: vec![AddressWindow { start: 0, end: 256 }],
+            max_outstanding: Some(8),
+            ..Constraints::default()
+        };
+        let map = MemoryMap::default();
+        for name in traffic::GENERATORS {
+            let mut generator = traffic::by_name(name, constraints.clone()).unwrap();
+            let mut rng = StdRng::seed_from_u64(3);
+            let mut shadow = ShadowMem::new(0, 4, map.clone());
+            // the chips, written when the DUT completes a write
+            let mut dut = ShadowMem::new(0, 4, map.clone());
+            let mut writes = Scoreboard::new("write");
+            let mut reads = Scoreboard::new("read");
+            let mut verified = 0;
+            for tick in 0..2000 {
+                // the agent polls for AW, then for AR
+                for write in [true, false] {
+                    let clear_of = in_flight(&map, &layout, &writes, &reads);
+                    let mut ctx = TrafficContext {
+                        layout: &layout,
+                        rng: &mut rng,
+                        tick,
+                        outstanding: writes.outstanding() + reads.outstanding(),
+                        in_flight: &clear_of,
+                    };
+                    if write {
+                        if let Some(payload) = generator.next_write(&mut ctx) {
+                            shadow.write_mem_axi(payload.clone());
+                            writes.issue(payload.id, payload);
+                        }
+                    } else if let Some(payload) = generator.next_read(&mut ctx) {
+                        reads.issue(payload.id, PendingRead::new(payload, &shadow));
+                    }
+                }
+
+                // the DUT completes the oldest transaction of a random ID
+                let ids: Vec<(bool, u8)> = writes
+                    .outstanding_by_id()
+                    .map(|(id, _)| (true, id))
+                    .chain(reads.outstanding_by_id().map(|(id, _)| (false, id)))
+                    .collect();
+                if ids.is_empty() {
+                    continue;
+                }
+                match ids[rng.gen_range(0..ids.len())] {
+                    (true, id) => {
+                        let payload = writes.complete(id, |_| true).unwrap();
+                        dut.write_mem_axi(payload.clone());
+                        generator.write_done(&payload);
+                    }
+                    (false, id) => {
+                        let read = reads.complete(id, |_| true).unwrap();
+                        assert_eq!(dut.read_mem_axi(&read.payload), read.expected, "{name}");
+                        verified += 1;
+                        generator.read_done(&read.payload);
+                    }
+                }
+            }
+            assert!(verified > 100, "{name}: {verified} read(s)");
+        }
+    }
+
+    #[test]
+    fn directed_scripts() {
+        let layout = test_layout();
+        // the expectations of the scripts hold in the shadow memory
+        for (name, expected_reads) in [("sparse-strobes", 4), ("unaligned", 4)] {
+            let path = format!("{}/scripts/{name}.script", env!("CARGO_MANIFEST_DIR"));
+            let commands = script::parse(&std::fs::read_to_string(path).unwrap(), &layout).unwrap();
+            let mut mem = ShadowMem::new(0, 4, MemoryMap::default());
+            let mut reads = 0;
+            for command in commands {
+                match command {
+                    Command::Write(payload) => mem.write_mem_axi(payload),
+                    Command::Read { payload, expect } => {
+                        assert_eq!(Some(mem.read_mem_axi(&payload)), expect, "{payload:x?}");
+                        reads += 1;
+                    }
+                    _ => {}
+                }
+            }
+            assert_eq!(reads, expected_reads, "{name}");
+        }
     }
 }
@@ -1,8 +1,22 @@
 use clap::Parser;
 use common::CommonArgs;
+use std::ops::Range;
+use std::path::PathBuf;
 
+pub mod check;
+pub mod command_log;
 pub mod dpi;
 pub mod drive;
+pub mod mode;
+pub mod protocol;
+pub mod refresh;
+pub mod registry;
+pub mod replay;
+pub mod scoreboard;
+pub mod script;
+pub mod sdram;
+pub mod timing;
+pub mod traffic;
 
 #[derive(Parser)]
 pub(crate) struct OfflineArgs {
@@ -17,11 +31,107 @@ pub(crate) struct OfflineArgs {
     #[arg(long, default_value = "")]
     pub dump_range: String,
 
-    #[arg(long, hide = true, default_value = env!("TIMEOUT"))]
-    pub timeout: u64,
-    
-    #[arg(long, hide = true,default_value = env!("CLOCK_FLIP_TIME"))]
-    clock_flip_time: u64,
+    /// Name of an `AXI4MasterAgent` driven by the testbench, repeatable in the
+    /// order of their `channelId`; with more than one, `--script`, `--replay`,
+    /// `--record` and `--constraints` name one file per agent, e.g.
+    /// `fail.trace` is `fail.<agent>.trace`
+    #[arg(long = "agent", default_value = "axi4Probe", value_parser = clap::builder::PossibleValuesParser::new(dpi::AGENTS))]
+    pub agents: Vec<String>,
+
+    /// Byte value returned when reading memory that was never written
+    #[arg(long, default_value_t = 0)]
+    pub mem_fill: u8,
+
+    /// Address window `<start>..<end>` where SLVERR/DECERR responses are expected, repeatable
+    #[arg(long, value_parser = check::parse_window)]
+    pub expect_error: Vec<Range<u64>>,
+
+    /// Traffic generator producing the AXI stimulus
+    #[arg(long, default_value = "readback", value_parser = clap::builder::PossibleValuesParser::new(traffic::GENERATORS))]
+    pub traffic: String,
+
+    /// Memory map of the SDRAM chips, a single aliased 32 MiB chip when omitted
+    #[arg(long)]
+    pub memory_map: Option<PathBuf>,
+
+    /// SDRAM part modelled behind every chip select with its timing table, a
+    /// W9825G6KH-6 when omitted
+    #[arg(long)]
+    pub sdram_part: Option<PathBuf>,
+
+    /// Write every command sent to the SDRAM chips with its tick to this file
+    #[arg(long)]
+    pub sdram_log: Option<PathBuf>,
+
+    /// CAS latency the controller samples read data at, its `SDRAM_READ_LATENCY`
+    #[arg(long, default_value_t = 2)]
+    pub read_latency: u32,
+
+    /// Beats the controller transfers with every READ and WRITE, two 16-bit
+    /// halves of its 32-bit words
+    #[arg(long, default_value_t = 2)]
+    pub burst_length: u32,
+
+    /// Constraint file shaping the random traffic, e.g. configs/NarrowTransferConstraints.json
+    #[arg(long)]
+    pub constraints: Option<PathBuf>,
+
+    /// Directed test script to run instead of the traffic generator
+    #[arg(long)]
+    pub script: Option<PathBuf>,
+
+    /// Write every issued transaction with its issue tick to this trace file
+    #[arg(long)]
+    pub record: Option<PathBuf>,
+
+    /// Replay a trace written by `--record` instead of the traffic generator
+    #[arg(long, conflicts_with = "script")]
+    pub replay: Option<PathBuf>,
+
+    /// Share of the bursts deliberately broken to violate AXI4, which the DUT
+    /// has to react to as given by `--illegal-response`
+    #[arg(long, default_value_t = 0.0, value_parser = parse_probability)]
+    pub illegal_traffic: f64,
+
+    /// Reaction expected to illegal bursts
+    #[arg(long, value_enum, default_value_t = protocol::IllegalResponse::Accept)]
+    pub illegal_response: protocol::IllegalResponse,
+
+    /// Seed of the stimulus RNG, picked at random when omitted
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Finish after this many write bursts complete
+    #[arg(long)]
+    pub max_writes: Option<u64>,
+
+    /// Finish after this many read bursts complete
+    #[arg(long)]
+    pub max_reads: Option<u64>,
+
+    /// Finish after this many read bytes are verified against the shadow memory
+    #[arg(long)]
+    pub max_bytes: Option<u64>,
+
+    /// Finish after this many ticks
+    #[arg(long)]
+    pub max_ticks: Option<u64>,
+
+    /// Simulator time units per testbench tick
+    #[arg(long, hide = true, default_value = match option_env!("TIMESCALE") {
+        Some(timescale) => timescale,
+        None => "1",
+    })]
+    pub timescale: u64,
+}
+
+fn parse_probability(input: &str) -> Result<f64, String> {
+    let probability: f64 = input.parse().map_err(|e| format!("{e}"))?;
+    if !(0.0..=1.0).contains(&probability) {
+        return Err(format!("{probability} is not within 0..=1"));
+    }
+    Ok(probability)
 }
 
-pub const AXI_SIZE: u8 = 32;
+// `writePayloadSize` of the AXI4MasterAgent in SDRAMControllerTestBench
+pub const AXI_WRITE_PAYLOAD_SIZE: usize = 1;
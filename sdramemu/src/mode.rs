@@ -0,0 +1,275 @@
+//! The mode register of one SDRAM chip against what the controller assumes.
+//!
+//! The controller programs the mode register once during its initialisation,
+//! then samples read data `SDRAM_READ_LATENCY` cycles after READ and moves a
+//! fixed number of beats per READ or WRITE. A mode that differs returns other
+//! data than the controller expects, so it is flagged at the LOAD MODE
+//! REGISTER or burst that causes it rather than as a data mismatch later on.
+
+use std::fmt;
+
+use crate::sdram::{Command, ModeRegister};
+
+/// The mode the controller is built for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ControllerMode {
+    /// CAS latency read data is sampled at, `SDRAM_READ_LATENCY`
+    pub(crate) read_latency: u32,
+    /// beats of every READ and WRITE
+    pub(crate) burst_length: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum ModeMismatch {
+    CasLatency {
+        programmed: u32,
+        read_latency: u32,
+    },
+    /// `None` for a full page burst
+    BurstLength {
+        programmed: Option<u32>,
+        expected: u32,
+    },
+    /// write bursts access a single location, but the controller writes bursts
+    SingleWrite {
+        burst_length: u32,
+    },
+    /// a burst starting within the burst wraps around and is reordered
+    UnalignedBurst {
+        command: &'static str,
+        column: u32,
+        burst_length: u32,
+    },
+    /// `by` ended the burst of `command` after `beats` of its beats
+    BurstCut {
+        command: &'static str,
+        by: &'static str,
+        beats: u64,
+        burst_length: u32,
+    },
+}
+
+impl fmt::Display for ModeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModeMismatch::CasLatency {
+                programmed,
+                read_latency,
+            } => write!(
+                f,
+                "mode register sets CL={programmed}, the controller samples read data at {read_latency}"
+            ),
+            ModeMismatch::BurstLength {
+                programmed,
+                expected,
+            } => {
+                write!(f, "mode register sets BL=")?;
+                match programmed {
+                    Some(length) => write!(f, "{length}")?,
+                    None => write!(f, "page")?,
+                }
+                write!(f, ", the controller transfers {expected} beat(s) per burst")
+            }
+            ModeMismatch::SingleWrite { burst_length } => write!(
+                f,
+                "mode register sets single location writes, the controller writes {burst_length} beat(s)"
+            ),
+            ModeMismatch::UnalignedBurst {
+                command,
+                column,
+                burst_length,
+            } => write!(
+                f,
+                "{command} column {column:#x} is not aligned to BL={burst_length}"
+            ),
+            ModeMismatch::BurstCut {
+                command,
+                by,
+                beats,
+                burst_length,
+            } => write!(
+                f,
+                "{by} cuts the {command} burst after {beats} of {burst_length} beat(s)"
+            ),
+        }
+    }
+}
+
+/// The burst of the latest READ or WRITE.
+struct Burst {
+    command: &'static str,
+    bank: u8,
+    start: u64,
+    length: u32,
+}
+
+pub(crate) struct ModeChecker {
+    expected: ControllerMode,
+
+    // clock edges seen so far
+    cycle: u64,
+
+    burst: Option<Burst>,
+}
+
+impl ModeChecker {
+    pub(crate) fn new(expected: ControllerMode) -> Self {
+        ModeChecker {
+            expected,
+            cycle: 0,
+            burst: None,
+        }
+    }
+
+    /// Check the command registered at the next clock edge, `mode` is the mode
+    /// register in effect.
+    pub(crate) fn clock(
+        &mut self,
+        command: &Command,
+        mode: Option<ModeRegister>,
+    ) -> Vec<ModeMismatch> {
+        self.cycle += 1;
+        let mut mismatches = Vec::new();
+
+        // a new burst, BURST TERMINATE or PRECHARGE of its bank ends a burst
+        let ends_burst = match *command {
+            Command::Read { .. } | Command::Write { .. } | Command::BurstTerminate => true,
+            Command::Precharge { bank } => self
+                .burst
+                .as_ref()
+                .is_some_and(|burst| bank.is_none_or(|bank| bank == burst.bank)),
+            _ => false,
+        };
+        if ends_burst {
+            if let Some(burst) = self.burst.take() {
+                let beats = self.cycle - burst.start;
+                if beats < burst.length as u64 {
+                    mismatches.push(ModeMismatch::BurstCut {
+                        command: burst.command,
+                        by: command.name(),
+                        beats,
+                        burst_length: burst.length,
+                    });
+                }
+            }
+        }
+
+        match *command {
+            Command::LoadMode { mode } => {
+                // reserved values are reported by the chip
+                let Some(mode) = ModeRegister::decode(mode) else {
+                    return mismatches;
+                };
+                let expected = self.expected;
+                if mode.cas_latency != expected.read_latency {
+                    mismatches.push(ModeMismatch::CasLatency {
+                        programmed: mode.cas_latency,
+                        read_latency: expected.read_latency,
+                    });
+                }
+                if mode.burst_length != Some(expected.burst_length) {
+                    mismatches.push(ModeMismatch::BurstLength {
+                        programmed: mode.burst_length,
+                        expected: expected.burst_length,
+                    });
+                }
+                if mode.single_write && expected.burst_length > 1 {
+                    mismatches.push(ModeMismatch::SingleWrite {
+                        burst_length: expected.burst_length,
+                    });
+                }
+            }
+            Command::Read { bank, column, .. } | Command::Write { bank, column, .. } => {
+                // full page bursts are flagged at LOAD MODE REGISTER
+                let Some(length) = mode.and_then(|mode| mode.burst_length) else {
+                    return mismatches;
+                };
+                if column % length != 0 {
+                    mismatches.push(ModeMismatch::UnalignedBurst {
+                        command: command.name(),
+                        column,
+                        burst_length: length,
+                    });
+                }
+                let single = matches!(command, Command::Write { .. })
+                    && mode.is_some_and(|mode| mode.single_write);
+                self.burst = Some(Burst {
+                    command: command.name(),
+                    bank,
+                    start: self.cycle,
+                    length: if single { 1 } else { length },
+                });
+            }
+            _ => {}
+        }
+        mismatches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mode_and_bursts_against_the_controller() {
+        let mut checker = ModeChecker::new(ControllerMode {
+            read_latency: 2,
+            burst_length: 2,
+        });
+        // CL3, BL4 interleaved, single location writes
+        let lmr = Command::LoadMode {
+            mode: 0b10_0011_1010,
+        };
+        assert_eq!(
+            checker.clock(&lmr, None),
+            vec![
+                ModeMismatch::CasLatency {
+                    programmed: 3,
+                    read_latency: 2
+                },
+                ModeMismatch::BurstLength {
+                    programmed: Some(4),
+                    expected: 2
+                },
+                ModeMismatch::SingleWrite { burst_length: 2 },
+            ]
+        );
+        // the MODE_REGISTER of the controller, CL2 BL2 sequential
+        let mode = ModeRegister::decode(0b010_0001);
+        assert_eq!(
+            checker.clock(&Command::LoadMode { mode: 0b010_0001 }, None),
+            vec![]
+        );
+
+        let read = |column| Command::Read {
+            bank: 1,
+            column,
+            auto_precharge: false,
+        };
+        assert_eq!(checker.clock(&read(0x40), mode), vec![]);
+        assert_eq!(checker.clock(&Command::Nop, mode), vec![]);
+        assert_eq!(
+            checker.clock(&read(0x41), mode),
+            vec![ModeMismatch::UnalignedBurst {
+                command: "READ",
+                column: 0x41,
+                burst_length: 2
+            }]
+        );
+        // PRECHARGE of another bank leaves the burst running
+        assert_eq!(
+            checker.clock(&Command::Precharge { bank: Some(0) }, mode),
+            vec![]
+        );
+        checker.clock(&read(0x42), mode);
+        assert_eq!(
+            checker.clock(&Command::Precharge { bank: None }, mode),
+            vec![ModeMismatch::BurstCut {
+                command: "READ",
+                by: "PRECHARGE",
+                beats: 1,
+                burst_length: 2
+            }]
+        );
+    }
+}
@@ -0,0 +1,300 @@
+//! AXI4 protocol rules for the transactions issued to and answered by the DUT.
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::fmt;
+
+use crate::check::Resp;
+use crate::dpi::{AxiReadPayload, AxiWritePayload, PayloadLayout};
+
+const FIXED: u8 = 0;
+const INCR: u8 = 1;
+const WRAP: u8 = 2;
+
+/// A broken AXI4 rule.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Violation {
+    /// burst type 0b11 is reserved
+    ReservedBurst {
+        burst: u8,
+    },
+    /// a burst must not cross a 4 KiB boundary
+    Crosses4K {
+        addr: u32,
+        bytes: u32,
+    },
+    /// WRAP bursts take 2, 4, 8 or 16 beats
+    WrapLength {
+        beats: u32,
+    },
+    /// WRAP bursts start aligned to the transfer size
+    WrapUnaligned {
+        addr: u32,
+        size: u8,
+    },
+    FixedTooLong {
+        beats: u32,
+    },
+    SizeExceedsBus {
+        size: u8,
+        bus_bytes: u32,
+    },
+    /// the number of W beats disagrees with AWLEN
+    WriteDataCount {
+        beats: u32,
+        data: usize,
+    },
+    /// EXOKAY answering a transaction that was not exclusive
+    UnexpectedExOkay,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::ReservedBurst { burst } => write!(f, "reserved burst type {burst:#b}"),
+            Violation::Crosses4K { addr, bytes } => {
+                write!(f, "{bytes} bytes from {addr:#010x} cross a 4 KiB boundary")
+            }
+            Violation::WrapLength { beats } => write!(f, "WRAP burst of {beats} beats"),
+            Violation::WrapUnaligned { addr, size } => {
+                write!(f, "WRAP burst at {addr:#010x} unaligned to size {size}")
+            }
+            Violation::FixedTooLong { beats } => {
+                write!(f, "FIXED burst of {beats} beats, at most 16 allowed")
+            }
+            Violation::SizeExceedsBus { size, bus_bytes } => {
+                write!(f, "size {size} is wider than the {bus_bytes}-byte bus")
+            }
+            Violation::WriteDataCount { beats, data } => {
+                write!(f, "{data} W beats for a burst of {beats} beats")
+            }
+            Violation::UnexpectedExOkay => write!(f, "EXOKAY to a non-exclusive access"),
+        }
+    }
+}
+
+/// Rules shared by AW and AR.
+fn check_request(
+    addr: u32,
+    burst: u8,
+    len: u8,
+    size: u8,
+    layout: &PayloadLayout,
+) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let beats = len as u32 + 1;
+    let bytes_number = 1u32 << size;
+    let bus_bytes = layout.data_width / 8;
+    if bytes_number > bus_bytes {
+        violations.push(Violation::SizeExceedsBus { size, bus_bytes });
+    }
+    match burst {
+        FIXED if beats > 16 => violations.push(Violation::FixedTooLong { beats }),
+        FIXED => {}
+        INCR => {
+            // from the aligned start up to the end of the last transfer
+            let aligned = addr as u64 / bytes_number as u64 * bytes_number as u64;
+            let end = aligned + (bytes_number * beats) as u64;
+            if (addr as u64) >> 12 != (end - 1) >> 12 {
+                violations.push(Violation::Crosses4K {
+                    addr,
+                    bytes: (end - addr as u64) as u32,
+                });
+            }
+        }
+        WRAP => {
+            if !matches!(beats, 2 | 4 | 8 | 16) {
+                violations.push(Violation::WrapLength { beats });
+            }
+            if !addr.is_multiple_of(bytes_number) {
+                violations.push(Violation::WrapUnaligned { addr, size });
+            }
+        }
+        _ => violations.push(Violation::ReservedBurst { burst }),
+    }
+    violations
+}
+
+/// How the DUT answers bursts that break an AXI4 rule.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub(crate) enum IllegalResponse {
+    /// with SLVERR or DECERR, leaving memory untouched
+    Error,
+    /// with OKAY, carrying them out like legal ones, as the testbench
+    /// controller has no error responses
+    Accept,
+}
+
+pub(crate) fn check_write(payload: &AxiWritePayload, layout: &PayloadLayout) -> Vec<Violation> {
+    let mut violations = check_request(
+        payload.addr,
+        payload.burst,
+        payload.len,
+        payload.size,
+        layout,
+    );
+    let beats = payload.len as u32 + 1;
+    if payload.data.len() != beats as usize || payload.strb.len() != beats as usize {
+        violations.push(Violation::WriteDataCount {
+            beats,
+            data: payload.data.len(),
+        });
+    }
+    violations
+}
+
+pub(crate) fn check_read(payload: &AxiReadPayload, layout: &PayloadLayout) -> Vec<Violation> {
+    check_request(
+        payload.addr,
+        payload.burst,
+        payload.len,
+        payload.size,
+        layout,
+    )
+}
+
+/// Rules for a BRESP/RRESP, `lock` is the AxLOCK of the request.
+pub(crate) fn check_response(resp: Resp, lock: u8) -> Option<Violation> {
+    (resp == Resp::ExOkay && lock == 0).then_some(Violation::UnexpectedExOkay)
+}
+
+/// Request fields an illegal mutation may change.
+struct Request<'a> {
+    addr: &'a mut u32,
+    burst: &'a mut u8,
+    len: &'a mut u8,
+    size: &'a mut u8,
+}
+
+/// Break one rule that can be broken without changing the number of beats
+/// when `fixed_beats`, as a write has to carry the data of every beat.
+fn break_rule(request: Request, layout: &PayloadLayout, fixed_beats: bool, rng: &mut impl Rng) {
+    let bus_bytes = layout.data_width / 8;
+    let beats = *request.len as u32 + 1;
+    let mut rules: Vec<fn(Request, u32, &mut dyn rand::RngCore)> = vec![|r, _, _| {
+        *r.burst = 0b11;
+    }];
+    if bus_bytes < 128 {
+        rules.push(|r, bus_bytes, _| *r.size = bus_bytes.ilog2() as u8 + 1);
+    }
+    if !matches!(beats, 2 | 4 | 8 | 16) {
+        rules.push(|r, _, _| *r.burst = WRAP);
+    } else if !fixed_beats {
+        rules.push(|r, _, rng| {
+            *r.burst = WRAP;
+            *r.len = *[2u8, 4, 6, 14].choose(rng).unwrap();
+        });
+    }
+    if !fixed_beats {
+        rules.push(|r, _, _| {
+            *r.burst = FIXED;
+            *r.len = 16 + (*r.len % 16);
+        });
+        rules.push(|r, bus_bytes, _| {
+            // the last bytes before a 4 KiB boundary, then beyond it
+            *r.burst = INCR;
+            *r.len = (*r.len).max(1);
+            *r.size = (*r.size).min(bus_bytes.ilog2() as u8);
+            *r.addr = (*r.addr | 0xfff).wrapping_add(1).wrapping_sub(1 << *r.size);
+        });
+    }
+    let rule = rules[rng.gen_range(0..rules.len())];
+    rule(request, bus_bytes, rng);
+}
+
+/// Turn a legal write into one that breaks an AXI4 rule.
+pub(crate) fn make_illegal_write(
+    payload: &mut AxiWritePayload,
+    layout: &PayloadLayout,
+    rng: &mut impl Rng,
+) {
+    let request = Request {
+        addr: &mut payload.addr,
+        burst: &mut payload.burst,
+        len: &mut payload.len,
+        size: &mut payload.size,
+    };
+    break_rule(request, layout, true, rng);
+}
+
+/// Turn a legal read into one that breaks an AXI4 rule.
+pub(crate) fn make_illegal_read(
+    payload: &mut AxiReadPayload,
+    layout: &PayloadLayout,
+    rng: &mut impl Rng,
+) {
+    let request = Request {
+        addr: &mut payload.addr,
+        burst: &mut payload.burst,
+        len: &mut payload.len,
+        size: &mut payload.size,
+    };
+    break_rule(request, layout, false, rng);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dpi::test_layout;
+    use common::constraints::Constraints;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn request_rules() {
+        let layout = test_layout();
+        let check = |addr, burst, len, size| check_request(addr, burst, len, size, &layout);
+        assert_eq!(check(0xff8, INCR, 1, 2), vec![]);
+        assert_eq!(
+            check(0xffc, INCR, 1, 2),
+            vec![Violation::Crosses4K {
+                addr: 0xffc,
+                bytes: 8
+            }]
+        );
+        assert_eq!(check(0xffd, INCR, 0, 2), vec![]);
+        assert_eq!(
+            check(0x100, WRAP, 2, 2),
+            vec![Violation::WrapLength { beats: 3 }]
+        );
+        assert_eq!(
+            check(0x102, WRAP, 3, 2),
+            vec![Violation::WrapUnaligned {
+                addr: 0x102,
+                size: 2
+            }]
+        );
+        assert_eq!(
+            check(0x100, FIXED, 16, 0),
+            vec![Violation::FixedTooLong { beats: 17 }]
+        );
+        assert_eq!(
+            check(0x100, FIXED, 0, 3),
+            vec![Violation::SizeExceedsBus {
+                size: 3,
+                bus_bytes: 4
+            }]
+        );
+        assert_eq!(
+            check_response(Resp::ExOkay, 0),
+            Some(Violation::UnexpectedExOkay)
+        );
+        assert_eq!(check_response(Resp::ExOkay, 1), None);
+    }
+
+    #[test]
+    fn illegal_traffic_breaks_a_rule() {
+        let layout = test_layout();
+        let constraints = Constraints::default();
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..200 {
+            let mut write = AxiWritePayload::random(&layout, &constraints, &mut rng, 0);
+            let mut read = AxiReadPayload::from_write_payload(write.clone());
+            assert!(check_write(&write, &layout).is_empty());
+            assert!(check_read(&read, &layout).is_empty());
+            make_illegal_write(&mut write, &layout, &mut rng);
+            make_illegal_read(&mut read, &layout, &mut rng);
+            assert!(!check_write(&write, &layout).is_empty(), "{write:x?}");
+            assert!(!check_read(&read, &layout).is_empty(), "{read:x?}");
+        }
+    }
+}
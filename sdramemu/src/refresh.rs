@@ -0,0 +1,222 @@
+//! Refresh of one SDRAM chip: every AUTO REFRESH refreshes the next rows of
+//! the internal refresh counter, and each of them has to come round again
+//! within tREF.
+//!
+//! Time counts from the first AUTO REFRESH, as the chip holds no data before
+//! the controller finishes its initialisation.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use tracing::info;
+
+use crate::sdram::Command;
+use common::sdram_part::SdramPart;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum RefreshViolation {
+    /// `row` was refreshed `elapsed` cycles after its previous refresh
+    Late { row: u32, elapsed: u64, limit: u64 },
+    /// `row` was still waiting for its refresh at the end of the run
+    Missing { row: u32, elapsed: u64, limit: u64 },
+}
+
+impl fmt::Display for RefreshViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RefreshViolation::Late {
+                row,
+                elapsed,
+                limit,
+            } => write!(
+                f,
+                "row {row:#x} refreshed {elapsed} cycles after the previous refresh, tREF is {limit}"
+            ),
+            RefreshViolation::Missing {
+                row,
+                elapsed,
+                limit,
+            } => write!(
+                f,
+                "row {row:#x} not refreshed for {elapsed} cycles, tREF is {limit}"
+            ),
+        }
+    }
+}
+
+pub(crate) struct RefreshMonitor {
+    // tREF in clock cycles
+    limit: u64,
+
+    // the average interval between AUTO REFRESH commands, tREFI
+    interval: u64,
+
+    refresh_commands: u32,
+
+    rows_per_refresh: u32,
+
+    // clock edges seen so far
+    cycle: u64,
+
+    // cycles of the latest AUTO REFRESH of each refresh counter value, oldest first
+    refreshes: VecDeque<u64>,
+
+    count: u64,
+
+    first: Option<u64>,
+
+    worst_gap: u64,
+
+    // the most AUTO REFRESH commands the controller was behind tREFI
+    max_postponed: u64,
+
+    // only the first late refresh is reported, the rest are counted
+    late: u64,
+}
+
+impl RefreshMonitor {
+    pub(crate) fn new(part: &SdramPart) -> Self {
+        let timing = &part.timing;
+        let limit = timing.t_ref.cycles(timing.clock_mhz);
+        RefreshMonitor {
+            limit,
+            interval: (limit / timing.refresh_commands as u64).max(1),
+            refresh_commands: timing.refresh_commands,
+            rows_per_refresh: part.rows_per_refresh(),
+            cycle: 0,
+            refreshes: VecDeque::with_capacity(timing.refresh_commands as usize),
+            count: 0,
+            first: None,
+            worst_gap: 0,
+            max_postponed: 0,
+            late: 0,
+        }
+    }
+
+    /// The first row refreshed by the `index`-th AUTO REFRESH.
+    fn row(&self, index: u64) -> u32 {
+        (index % self.refresh_commands as u64) as u32 * self.rows_per_refresh
+    }
+
+    /// AUTO REFRESH commands due by `cycle`, at one per tREFI, but not issued.
+    fn postponed(&self, cycle: u64) -> u64 {
+        let Some(first) = self.first else {
+            return 0;
+        };
+        ((cycle - first) / self.interval + 1).saturating_sub(self.count)
+    }
+
+    /// Count the command registered at the next clock edge.
+    pub(crate) fn clock(&mut self, command: &Command) -> Option<RefreshViolation> {
+        self.cycle += 1;
+        if *command != Command::Refresh {
+            return None;
+        }
+        let cycle = self.cycle;
+        if let Some(previous) = self.refreshes.back() {
+            self.worst_gap = self.worst_gap.max(cycle - previous);
+        }
+        self.first.get_or_insert(cycle);
+
+        let mut violation = None;
+        if self.refreshes.len() == self.refresh_commands as usize {
+            // the previous refresh of the rows refreshed now
+            let previous = self.refreshes.pop_front().unwrap();
+            let elapsed = cycle - previous;
+            if elapsed > self.limit {
+                self.late += 1;
+                if self.late == 1 {
+                    violation = Some(RefreshViolation::Late {
+                        row: self.row(self.count),
+                        elapsed,
+                        limit: self.limit,
+                    });
+                }
+            }
+        }
+        self.refreshes.push_back(cycle);
+        self.count += 1;
+        self.max_postponed = self.max_postponed.max(self.postponed(cycle));
+        violation
+    }
+
+    /// Check the rows still waiting for their refresh, at the end of the run.
+    pub(crate) fn finish(&mut self) -> Option<RefreshViolation> {
+        let first = self.first?;
+        self.max_postponed = self.max_postponed.max(self.postponed(self.cycle));
+        // rows not refreshed yet in the first tREF are due from the first refresh
+        let oldest = match self.refreshes.len() == self.refresh_commands as usize {
+            true => *self.refreshes.front().unwrap(),
+            false => first,
+        };
+        let elapsed = self.cycle - oldest;
+        (elapsed > self.limit).then(|| RefreshViolation::Missing {
+            row: self.row(self.count),
+            elapsed,
+            limit: self.limit,
+        })
+    }
+
+    pub(crate) fn report(&self) {
+        if self.count == 0 {
+            info!("no AUTO REFRESH");
+            return;
+        }
+        info!(
+            "{} AUTO REFRESH, worst gap {} cycles (tREFI {}), at most {} postponed, {} late",
+            self.count, self.worst_gap, self.interval, self.max_postponed, self.late
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::sdram_part::TimingParameter;
+
+    #[test]
+    fn late_and_postponed_refresh() {
+        let mut part = SdramPart {
+            rows: 8,
+            ..SdramPart::default()
+        };
+        part.timing.refresh_commands = 4;
+        // 100 cycles at 50 MHz, a refresh every 25
+        part.timing.t_ref = TimingParameter {
+            ns: 2000.0,
+            clocks: 0,
+        };
+        let mut monitor = RefreshMonitor::new(&part);
+        fn run(monitor: &mut RefreshMonitor, idle: u64) -> Option<RefreshViolation> {
+            for _ in 0..idle {
+                assert_eq!(monitor.clock(&Command::Nop), None);
+            }
+            monitor.clock(&Command::Refresh)
+        }
+        for _ in 0..4 {
+            assert_eq!(run(&mut monitor, 24), None);
+        }
+        // rows 0 and 1 again, 175 cycles after their previous refresh
+        assert_eq!(
+            run(&mut monitor, 99),
+            Some(RefreshViolation::Late {
+                row: 0,
+                elapsed: 175,
+                limit: 100
+            })
+        );
+        assert_eq!(monitor.worst_gap, 100);
+        assert_eq!(monitor.max_postponed, 3);
+        // further late refreshes are only counted
+        assert_eq!(run(&mut monitor, 99), None);
+        assert_eq!(monitor.late, 2);
+
+        for _ in 0..150 {
+            monitor.clock(&Command::Nop);
+        }
+        assert!(matches!(
+            monitor.finish(),
+            Some(RefreshViolation::Missing { row: 4, .. })
+        ));
+    }
+}
@@ -0,0 +1,414 @@
+//! The `AXI4MasterAgent`s of a testbench, by agent name.
+//!
+//! Every agent drives its own port with its own generator and scoreboards,
+//! while all of them check against one shared shadow memory. The DPI functions
+//! of an agent are suffixed with its name, so each name in `--agent` needs
+//! them exported by `axi_agent_dpi!` in [`crate::dpi`].
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use svdpi::{get_time, SvScope};
+use tracing::{debug, error, info, info_span, trace};
+
+use crate::check::{CheckError, CheckReport};
+use crate::command_log::CommandLog;
+use crate::dpi::*;
+use crate::drive::*;
+use crate::mode::{ControllerMode, ModeChecker};
+use crate::refresh::RefreshMonitor;
+use crate::sdram::{self, Pins, SdramDevice};
+use crate::timing::TimingChecker;
+use crate::OfflineArgs;
+use common::memory_map::MemoryMap;
+use common::rtl_config::RTLConfig;
+use common::sdram_part::SdramPart;
+
+pub(crate) struct Registry {
+    // SvScope from cosim_init
+    #[cfg_attr(not(feature = "trace"), allow(dead_code))]
+    scope: SvScope,
+
+    #[cfg(feature = "trace")]
+    wave_path: String,
+    #[cfg(feature = "trace")]
+    dump_start: u64,
+    #[cfg(feature = "trace")]
+    dump_end: u64,
+    #[cfg(feature = "trace")]
+    dump_started: bool,
+
+    layout: PayloadLayout,
+
+    cs_width: u32,
+
+    clock_flip_time: u64,
+
+    seed: u64,
+
+    shadow_mem: SharedMem,
+
+    agents: BTreeMap<String, Driver>,
+
+    // the SDRAM chip of every chip select
+    devices: Vec<SdramDevice>,
+
+    // JEDEC timing of the commands to every chip select
+    timing: Vec<TimingChecker>,
+
+    // the mode register of every chip select against the controller
+    modes: Vec<ModeChecker>,
+
+    // AUTO REFRESH of every chip select
+    refresh: Vec<RefreshMonitor>,
+
+    // commands to the SDRAM chips, from --sdram-log
+    command_log: Option<CommandLog>,
+
+    // commands the SDRAM chips could not execute in time
+    checks: CheckReport,
+}
+
+#[cfg(feature = "trace")]
+fn parse_range(input: &str) -> (u64, u64) {
+    if input.is_empty() {
+        return (0, 0);
+    }
+
+    let parts: Vec<&str> = input.split(",").collect();
+
+    if parts.len() != 1 && parts.len() != 2 {
+        error!("invalid dump wave range: `{input}` was given");
+        return (0, 0);
+    }
+
+    const INVALID_NUMBER: &str = "invalid number";
+
+    if parts.len() == 1 {
+        return (parts[0].parse().expect(INVALID_NUMBER), 0);
+    }
+
+    if parts[0].is_empty() {
+        return (0, parts[1].parse().expect(INVALID_NUMBER));
+    }
+
+    let start = parts[0].parse().expect(INVALID_NUMBER);
+    let end = parts[1].parse().expect(INVALID_NUMBER);
+    if start > end {
+        panic!("dump start is larger than end: `{input}`");
+    }
+
+    (start, end)
+}
+
+/// The file of `agent` given `path` on the command line: with more than one
+/// agent, `fail.trace` becomes `fail.<agent>.trace`.
+fn agent_path(path: &Path, agent: &str, agents: usize) -> PathBuf {
+    if agents == 1 {
+        return path.to_owned();
+    }
+    let mut name = path.file_stem().map(OsString::from).unwrap_or_default();
+    name.push(".");
+    name.push(agent);
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+impl Registry {
+    pub(crate) fn new(scope: SvScope, args: &OfflineArgs, config: &RTLConfig) -> Self {
+        #[cfg(feature = "trace")]
+        let (dump_start, dump_end) = parse_range(&args.dump_range);
+
+        let seed = args.seed.unwrap_or_else(rand::random);
+        let memory_map = match &args.memory_map {
+            Some(path) => MemoryMap::load(path).unwrap(),
+            None => MemoryMap::default(),
+        };
+        let shadow_mem = Arc::new(Mutex::new(ShadowMem::new(
+            args.mem_fill,
+            config.axi().data_width_in_bytes(),
+            memory_map,
+        )));
+
+        let part = match &args.sdram_part {
+            Some(path) => SdramPart::load(path).unwrap(),
+            None => SdramPart::default(),
+        };
+        let devices = (0..config.sdram().cs_width)
+            .map(|_| SdramDevice::new(part.clone(), args.mem_fill))
+            .collect();
+        let timing = (0..config.sdram().cs_width)
+            .map(|_| TimingChecker::new(&part))
+            .collect();
+        let controller_mode = ControllerMode {
+            read_latency: args.read_latency,
+            burst_length: args.burst_length,
+        };
+        let modes = (0..config.sdram().cs_width)
+            .map(|_| ModeChecker::new(controller_mode))
+            .collect();
+        let refresh = (0..config.sdram().cs_width)
+            .map(|_| RefreshMonitor::new(&part))
+            .collect();
+        let command_log = args.sdram_log.as_ref().map(|path| {
+            CommandLog::create(path, &part).unwrap_or_else(|e| {
+                panic!("failed to create SDRAM command log {}: {e}", path.display())
+            })
+        });
+
+        let mut agents = BTreeMap::new();
+        for (channel_id, name) in args.agents.iter().enumerate() {
+            // every agent draws from its own stream of the seed
+            let driver = Driver::new(
+                name,
+                channel_id as u64,
+                args,
+                config,
+                shadow_mem.clone(),
+                seed.wrapping_add(channel_id as u64),
+                |path| agent_path(path, name, args.agents.len()),
+            );
+            assert!(
+                agents.insert(name.clone(), driver).is_none(),
+                "agent {name} is given more than once"
+            );
+        }
+
+        Registry {
+            scope,
+
+            #[cfg(feature = "trace")]
+            wave_path: args.wave_path.to_owned(),
+            #[cfg(feature = "trace")]
+            dump_start,
+            #[cfg(feature = "trace")]
+            dump_end,
+            #[cfg(feature = "trace")]
+            dump_started: false,
+            layout: PayloadLayout::new(config.axi()),
+            cs_width: config.sdram().cs_width,
+            clock_flip_time: config.test_verbatim_parameter.clock_flip_tick * args.timescale,
+            seed,
+            shadow_mem,
+            agents,
+            devices,
+            timing,
+            modes,
+            refresh,
+            command_log,
+            checks: CheckReport::default(),
+        }
+    }
+
+    fn get_tick(&self) -> u64 {
+        get_time() / self.clock_flip_time
+    }
+
+    pub(crate) fn agent(&mut self, name: &str) -> &mut Driver {
+        self.agents
+            .get_mut(name)
+            .unwrap_or_else(|| panic!("agent {name} is not registered, pass --agent {name}"))
+    }
+
+    /// Clock the SDRAM chip of chip select `cs`, returns the value it drives on DQ.
+    pub(crate) fn sdram_clock(&mut self, cs: u8, pins: &Pins) -> u32 {
+        // CKE low suspends the clock, but not the time the timing counts
+        let command = match pins.cke {
+            true => sdram::Command::decode(pins, self.devices[cs as usize].part()),
+            false => sdram::Command::Nop,
+        };
+        if !matches!(command, sdram::Command::Deselect | sdram::Command::Nop) {
+            let tick = self.get_tick();
+            debug!("[{tick}] cs {cs}: {command}");
+            if let Some(log) = &mut self.command_log {
+                log.record(tick, cs, &command);
+            }
+        }
+        let device = &mut self.devices[cs as usize];
+        let violations = self.timing[cs as usize].clock(&command, device.mode());
+        let mismatches = self.modes[cs as usize].clock(&command, device.mode());
+        let refresh = self.refresh[cs as usize].clock(&command);
+        let (dq, errors) = device.clock(pins);
+        if !errors.is_empty()
+            || !violations.is_empty()
+            || !mismatches.is_empty()
+            || refresh.is_some()
+        {
+            let tick = self.get_tick();
+            for error in errors {
+                self.checks.record(tick, CheckError::Sdram { cs, error });
+            }
+            for violation in violations {
+                self.checks
+                    .record(tick, CheckError::Timing { cs, violation });
+            }
+            for mismatch in mismatches {
+                self.checks.record(tick, CheckError::Mode { cs, mismatch });
+            }
+            if let Some(violation) = refresh {
+                self.checks
+                    .record(tick, CheckError::Refresh { cs, violation });
+            }
+        }
+        dq
+    }
+
+    pub(crate) fn init(&mut self) {
+        info!("seed: {}", self.seed);
+        info!(
+            "AXI bus: {}-bit data, {}-bit address, {}-bit id; {} chip selects",
+            self.layout.data_width, self.layout.addr_width, self.layout.id_width, self.cs_width
+        );
+        let shadow_mem = self.shadow_mem.lock().unwrap();
+        let map = &shadow_mem.map;
+        info!(
+            "memory map: {} x {} MiB at {:#x}, {}",
+            map.chip_selects,
+            map.chip_select_size >> 20,
+            map.base,
+            if map.alias {
+                "aliased"
+            } else {
+                "out of range is an error"
+            }
+        );
+        drop(shadow_mem);
+        let part = self.devices[0].part();
+        info!(
+            "SDRAM: {} {} banks x {} rows x {} columns x {} bits per chip select, at {} MHz",
+            part.name, part.banks, part.rows, part.columns, part.data_width, part.timing.clock_mhz
+        );
+        for driver in self.agents.values() {
+            driver.span.in_scope(|| driver.init());
+        }
+
+        #[cfg(feature = "trace")]
+        if self.dump_start == 0 {
+            self.start_dump_wave();
+            self.dump_started = true;
+        }
+    }
+
+    /// Stops once every agent drained, or any of them timed out.
+    pub(crate) fn watchdog(&mut self) -> u8 {
+        let tick = self.get_tick();
+        let verdicts: Vec<u8> = self
+            .agents
+            .values_mut()
+            .map(|driver| driver.span.clone().in_scope(|| driver.watchdog()))
+            .collect();
+
+        if verdicts.contains(&WATCHDOG_TIMEOUT) {
+            self.report();
+            error!("replay with --seed {}", self.seed);
+            return WATCHDOG_TIMEOUT;
+        }
+
+        if !verdicts.contains(&WATCHDOG_CONTINUE) {
+            return self.finish();
+        }
+
+        #[cfg(feature = "trace")]
+        if self.dump_end != 0 && tick > self.dump_end {
+            info!("[{tick}] run to dump end, exiting");
+            return self.finish();
+        }
+
+        #[cfg(feature = "trace")]
+        if !self.dump_started && tick >= self.dump_start {
+            self.start_dump_wave();
+            self.dump_started = true;
+        }
+        trace!("[{tick}] watchdog continue");
+        WATCHDOG_CONTINUE
+    }
+
+    /// Report the run and stop it, failed when a check of any agent or SDRAM
+    /// chip failed.
+    fn finish(&mut self) -> u8 {
+        self.report();
+        if self.agents.values().all(Driver::passed) && self.checks.passed() {
+            info!("TEST PASSED");
+            WATCHDOG_FINISH
+        } else {
+            error!("TEST FAILED, replay with --seed {}", self.seed);
+            WATCHDOG_FAIL
+        }
+    }
+
+    /// Summarize the run, called once before the simulation stops.
+    fn report(&mut self) {
+        let shadow_mem = self.shadow_mem.lock().unwrap();
+        info!(
+            "shadow memory: {} pages allocated, {} KiB resident",
+            shadow_mem.allocated_pages(),
+            shadow_mem.allocated_bytes() / 1024
+        );
+        drop(shadow_mem);
+        for driver in self.agents.values() {
+            driver.span.in_scope(|| driver.report());
+        }
+        let failed: Vec<&str> = self
+            .agents
+            .iter()
+            .filter(|(_, driver)| !driver.passed())
+            .map(|(name, _)| name.as_str())
+            .collect();
+        if self.agents.len() > 1 && !failed.is_empty() {
+            error!("agent(s) with failed checks: {}", failed.join(", "));
+        }
+        let tick = self.get_tick();
+        for (cs, monitor) in self.refresh.iter_mut().enumerate() {
+            let cs = cs as u8;
+            if let Some(violation) = monitor.finish() {
+                self.checks
+                    .record(tick, CheckError::Refresh { cs, violation });
+            }
+            info_span!("sdram", cs).in_scope(|| monitor.report());
+        }
+        // commands the SDRAM chips could not execute
+        if !self.checks.passed() {
+            self.checks.report();
+        }
+    }
+
+    #[cfg(feature = "trace")]
+    fn start_dump_wave(&mut self) {
+        dump_wave(self.scope, &self.wave_path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_per_agent() {
+        let path = Path::new("out/fail.trace");
+        assert_eq!(agent_path(path, "axi4Probe", 1), path);
+        assert_eq!(
+            agent_path(path, "axi4Probe", 2),
+            Path::new("out/fail.axi4Probe.trace")
+        );
+        assert_eq!(
+            agent_path(Path::new("fail"), "port1", 2),
+            Path::new("fail.port1")
+        );
+    }
+
+    #[test]
+    fn agents_need_dpi_functions() {
+        use clap::{error::ErrorKind, Parser};
+        let parse = |agent| {
+            OfflineArgs::try_parse_from(["sdramemu", "--config", "tb.json", "--agent", agent])
+        };
+        let kind = |agent| parse(agent).err().map(|e| e.kind());
+        assert_ne!(kind("axi4Probe"), Some(ErrorKind::InvalidValue));
+        assert_eq!(kind("port1"), Some(ErrorKind::InvalidValue));
+    }
+}
@@ -0,0 +1,185 @@
+//! Recorded AXI traces, one issued transaction per line:
+//!
+//! ```text
+//! # seed 42
+//! 12 write id=1 addr=0x1000 burst=incr size=2 data=deadbeef strb=f ...
+//! 15 read id=1 addr=0x1000 burst=incr size=2 len=0 ...
+//! ```
+//!
+//! Each line is the issue tick followed by the transaction in the script
+//! format of [`crate::script`], so a trace does not depend on the RNG and
+//! replays the same stimulus on any simulator.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, LineWriter, Write};
+use std::path::Path;
+
+use crate::dpi::{AxiReadPayload, AxiWritePayload, PayloadLayout};
+use crate::script::{self, Command};
+use crate::traffic::{TrafficContext, TrafficGenerator};
+
+/// Appends every issued transaction to a trace file.
+pub(crate) struct Recorder {
+    // line buffered, so the trace survives a simulator that is killed
+    file: LineWriter<File>,
+}
+
+impl Recorder {
+    pub(crate) fn create(path: &Path, seed: u64) -> io::Result<Self> {
+        let mut file = LineWriter::new(File::create(path)?);
+        writeln!(file, "# seed {seed}")?;
+        Ok(Recorder { file })
+    }
+
+    pub(crate) fn record(&mut self, tick: u64, command: &Command) {
+        writeln!(self.file, "{tick} {command}").expect("failed to write trace");
+    }
+}
+
+pub(crate) fn parse(trace: &str, layout: &PayloadLayout) -> Result<Vec<(u64, Command)>, String> {
+    let parse_line = |line: &str| -> Result<Option<(u64, Command)>, String> {
+        let line = line.split('#').next().unwrap().trim();
+        let Some((tick, command)) = line.split_once(char::is_whitespace) else {
+            return match line {
+                "" => Ok(None),
+                _ => Err("expected `<tick> <transaction>`".to_owned()),
+            };
+        };
+        let tick = tick
+            .parse()
+            .map_err(|e| format!("invalid tick `{tick}`: {e}"))?;
+        // illegal transactions are replayed as recorded, the protocol checker
+        // decides whether they may be issued
+        match script::parse_command(command, layout, false)? {
+            Some(command @ (Command::Write(_) | Command::Read { .. })) => Ok(Some((tick, command))),
+            _ => Err("only write and read can be replayed".to_owned()),
+        }
+    };
+    trace
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            parse_line(line)
+                .map_err(|e| format!("line {}: {e}", index + 1))
+                .transpose()
+        })
+        .collect()
+}
+
+/// Issues the transactions of a trace in their recorded order, none of them
+/// earlier than its recorded tick.
+pub(crate) struct ReplayGenerator {
+    transactions: VecDeque<(u64, Command)>,
+}
+
+impl ReplayGenerator {
+    pub(crate) fn new(transactions: Vec<(u64, Command)>) -> Self {
+        ReplayGenerator {
+            transactions: transactions.into(),
+        }
+    }
+
+    fn due(&self, ctx: &TrafficContext) -> Option<&Command> {
+        let (tick, command) = self.transactions.front()?;
+        (ctx.tick >= *tick).then_some(command)
+    }
+}
+
+impl TrafficGenerator for ReplayGenerator {
+    fn next_write(&mut self, ctx: &mut TrafficContext) -> Option<AxiWritePayload> {
+        let Command::Write(_) = self.due(ctx)? else {
+            return None;
+        };
+        let Some((_, Command::Write(payload))) = self.transactions.pop_front() else {
+            unreachable!()
+        };
+        Some(payload)
+    }
+
+    fn next_read(&mut self, ctx: &mut TrafficContext) -> Option<AxiReadPayload> {
+        let Command::Read { .. } = self.due(ctx)? else {
+            return None;
+        };
+        let Some((_, Command::Read { payload, .. })) = self.transactions.pop_front() else {
+            unreachable!()
+        };
+        Some(payload)
+    }
+
+    fn finished(&self) -> bool {
+        self.transactions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dpi::test_layout;
+    use crate::traffic::InFlight;
+    use common::constraints::Constraints;
+    use common::memory_map::MemoryMap;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn ctx<'a>(
+        layout: &'a PayloadLayout,
+        rng: &'a mut StdRng,
+        in_flight: &'a InFlight<'a>,
+        tick: u64,
+    ) -> TrafficContext<'a> {
+        TrafficContext {
+            layout,
+            rng,
+            tick,
+            outstanding: 0,
+            in_flight,
+        }
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let layout = test_layout();
+        let mut rng = StdRng::seed_from_u64(7);
+        let write = AxiWritePayload::random(&layout, &Constraints::default(), &mut rng, 2);
+        let read = AxiReadPayload::from_write_payload(write.clone());
+
+        let path =
+            std::env::temp_dir().join(format!("sdramemu-replay-{}.trace", std::process::id()));
+        let mut recorder = Recorder::create(&path, 7).unwrap();
+        recorder.record(3, &Command::Write(write.clone()));
+        recorder.record(
+            9,
+            &Command::Read {
+                payload: read.clone(),
+                expect: None,
+            },
+        );
+        drop(recorder);
+        let trace = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let map = MemoryMap::default();
+        let in_flight = InFlight::new(&map, 4);
+        let mut replay = ReplayGenerator::new(parse(&trace, &layout).unwrap());
+        assert_eq!(
+            replay.next_write(&mut ctx(&layout, &mut rng, &in_flight, 2)),
+            None
+        );
+        assert_eq!(
+            replay.next_write(&mut ctx(&layout, &mut rng, &in_flight, 3)),
+            Some(write)
+        );
+        assert_eq!(
+            replay.next_read(&mut ctx(&layout, &mut rng, &in_flight, 8)),
+            None
+        );
+        assert_eq!(
+            replay.next_read(&mut ctx(&layout, &mut rng, &in_flight, 9)),
+            Some(read)
+        );
+        assert!(replay.finished());
+
+        assert!(parse("12 wait 3", &layout).is_err());
+        assert!(parse("write addr=0", &layout).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::{BTreeMap, VecDeque};
+use tracing::{info, warn};
+
+/// Outstanding AXI transactions, ordered per ID.
+///
+/// AXI4 only guarantees ordering between transactions sharing an ID, so
+/// responses are matched against the queue of their own ID only. Within an ID
+/// they answer the oldest transaction, whatever their data.
+pub(crate) struct Scoreboard<T> {
+    name: &'static str,
+    outstanding: BTreeMap<u8, VecDeque<T>>,
+    completed: u64,
+    unknown_ids: u64,
+    // responses that fit a younger transaction of their ID instead of the oldest
+    ordering_violations: BTreeMap<u8, u64>,
+}
+
+impl<T> Scoreboard<T> {
+    pub(crate) fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            outstanding: BTreeMap::new(),
+            completed: 0,
+            unknown_ids: 0,
+            ordering_violations: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn issue(&mut self, id: u8, transaction: T) {
+        self.outstanding
+            .entry(id)
+            .or_default()
+            .push_back(transaction);
+    }
+
+    /// Retire the oldest transaction with `id`, `None` when nothing is
+    /// outstanding with it. A response that `matches` a younger transaction
+    /// but not the oldest is counted as an ordering violation of `id`.
+    pub(crate) fn complete(&mut self, id: u8, matches: impl Fn(&T) -> bool) -> Option<T> {
+        let Some(queue) = self.outstanding.get_mut(&id) else {
+            self.unknown_ids += 1;
+            return None;
+        };
+        if !matches(&queue[0]) {
+            if let Some(index) = queue.iter().skip(1).position(&matches) {
+                *self.ordering_violations.entry(id).or_default() += 1;
+                warn!(
+                    "{}: id {id} completed out of order, {} older transaction(s) outstanding",
+                    self.name,
+                    index + 1
+                );
+            }
+        }
+        let transaction = queue.pop_front().unwrap();
+        if queue.is_empty() {
+            self.outstanding.remove(&id);
+        }
+        self.completed += 1;
+        Some(transaction)
+    }
+
+    /// The transaction a response with `id` is expected to answer.
+    pub(crate) fn oldest(&self, id: u8) -> Option<&T> {
+        self.outstanding.get(&id).and_then(VecDeque::front)
+    }
+
+    pub(crate) fn outstanding(&self) -> usize {
+        self.outstanding.values().map(VecDeque::len).sum()
+    }
+
+    /// Outstanding transactions of each ID, oldest first.
+    pub(crate) fn outstanding_by_id(&self) -> impl Iterator<Item = (u8, &VecDeque<T>)> {
+        self.outstanding.iter().map(|(id, queue)| (*id, queue))
+    }
+
+    pub(crate) fn report(&self) {
+        info!(
+            "{}: {} completed, {} outstanding, {} unknown id(s)",
+            self.name,
+            self.completed,
+            self.outstanding(),
+            self.unknown_ids
+        );
+        for (id, count) in &self.ordering_violations {
+            warn!("{}: id {id} had {count} ordering violation(s)", self.name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_complete_independently() {
+        let mut sb = Scoreboard::new("test");
+        sb.issue(1, "a0");
+        sb.issue(2, "b0");
+        sb.issue(1, "a1");
+        assert_eq!(sb.complete(2, |_| true), Some("b0"));
+        assert_eq!(sb.complete(1, |_| true), Some("a0"));
+        assert_eq!(sb.complete(1, |_| true), Some("a1"));
+        assert_eq!(sb.outstanding(), 0);
+        assert!(sb.ordering_violations.is_empty());
+    }
+
+    #[test]
+    fn unknown_id_is_flagged() {
+        let mut sb = Scoreboard::new("test");
+        sb.issue(1, "a0");
+        assert_eq!(sb.complete(3, |_| true), None);
+        assert_eq!(sb.unknown_ids, 1);
+        assert_eq!(sb.outstanding(), 1);
+        assert_eq!(sb.complete(1, |_| true), Some("a0"));
+        assert_eq!(sb.complete(1, |_| true), None);
+        assert_eq!(sb.unknown_ids, 2);
+    }
+
+    #[test]
+    fn reordering_within_id_is_flagged() {
+        let mut sb = Scoreboard::new("test");
+        sb.issue(1, "a0");
+        sb.issue(1, "a1");
+        // the oldest is retired even when the response fits a younger one
+        assert_eq!(sb.complete(1, |t| *t == "a1"), Some("a0"));
+        assert_eq!(sb.ordering_violations[&1], 1);
+        // a response fitting nothing is left to the caller to report
+        assert_eq!(sb.complete(1, |_| false), Some("a1"));
+        assert_eq!(sb.ordering_violations[&1], 1);
+    }
+}
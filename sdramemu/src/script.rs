@@ -0,0 +1,520 @@
+//! Directed test scripts.
+//!
+//! One command per line, `#` starts a comment. Numbers accept a `0x` prefix,
+//! beat data is given as one hex value per beat, separated by commas.
+//!
+//! ```text
+//! write addr=0x1000 burst=incr size=2 data=deadbeef,01020304 strb=f,3 id=1
+//! barrier
+//! read addr=0x1000 burst=wrap size=2 len=7 expect=deadbeef,01020304,...
+//! wait 100
+//! ```
+//!
+//! `write` takes its burst length from the number of `data` beats and has no
+//! `len`. Each beat is the raw value of the whole bus, of which a narrow
+//! transfer uses the lanes selected by its address. `strb` defaults to all of those lanes and may
+//! enable any subset of them. `read` data is always checked against the
+//! shadow memory, `expect` additionally pins each transfer to a fixed value.
+//! `wait` pauses issuing for a number of ticks, `barrier` until every
+//! outstanding transaction completed.
+//!
+//! The remaining AX fields can be set with `cache`, `lock`, `prot`, `qos`,
+//! `region` and `user`, and W user bits per beat with `wuser`; they default to 0.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::dpi::{burst_lanes, AxiReadPayload, AxiWritePayload, BeatLanes, PayloadLayout};
+use crate::protocol;
+use crate::traffic::{TrafficContext, TrafficGenerator};
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Command {
+    Write(AxiWritePayload),
+    Read {
+        payload: AxiReadPayload,
+        // bytes of each beat's active lanes, concatenated
+        expect: Option<Vec<u8>>,
+    },
+    Wait(u64),
+    Barrier,
+}
+
+fn parse_number(s: &str) -> Result<u64, String> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|e| format!("invalid number `{s}`: {e}"))
+}
+
+/// The value of `key`, which has to fit into its field.
+fn parse_field<T: TryFrom<u64>>(key: &str, s: &str) -> Result<T, String> {
+    let number = parse_number(s)?;
+    T::try_from(number).map_err(|_| format!("{key}={number} does not fit into its field"))
+}
+
+const BURSTS: [&str; 3] = ["fixed", "incr", "wrap"];
+
+/// A burst type by name, or the raw AxBURST value.
+fn parse_burst(s: &str) -> Result<u8, String> {
+    match BURSTS.iter().position(|burst| *burst == s) {
+        Some(burst) => Ok(burst as u8),
+        None => parse_number(s)
+            .ok()
+            .filter(|burst| *burst <= 0b11)
+            .map(|burst| burst as u8)
+            .ok_or_else(|| format!("unknown burst type `{s}`")),
+    }
+}
+
+fn format_burst(burst: u8) -> String {
+    match BURSTS.get(burst as usize) {
+        Some(name) => name.to_string(),
+        None => burst.to_string(),
+    }
+}
+
+/// Parse a hex beat value into `bus_bytes` little-endian bytes.
+fn parse_beat(s: &str, bus_bytes: usize) -> Result<Vec<u8>, String> {
+    let digits = s.strip_prefix("0x").unwrap_or(s);
+    let padded = if digits.len() % 2 == 1 {
+        format!("0{digits}")
+    } else {
+        digits.to_owned()
+    };
+    let mut bytes = hex::decode(&padded).map_err(|e| format!("invalid beat `{s}`: {e}"))?;
+    bytes.reverse();
+    if bytes.len() > bus_bytes {
+        return Err(format!("beat `{s}` is wider than the {bus_bytes}-byte bus"));
+    }
+    bytes.resize(bus_bytes, 0);
+    Ok(bytes)
+}
+
+/// Parse one line, rejecting transactions that break an AXI4 rule when
+/// `legal_only`.
+pub(crate) fn parse_command(
+    line: &str,
+    layout: &PayloadLayout,
+    legal_only: bool,
+) -> Result<Option<Command>, String> {
+    let line = line.split('#').next().unwrap().trim();
+    let mut words = line.split_whitespace();
+    let Some(op) = words.next() else {
+        return Ok(None);
+    };
+
+    if op == "wait" {
+        let ticks = words.next().ok_or("wait needs a tick count")?;
+        return Ok(Some(Command::Wait(parse_number(ticks)?)));
+    }
+    if op == "barrier" {
+        return Ok(Some(Command::Barrier));
+    }
+
+    let bus_bytes = layout.data_width as usize / 8;
+    let mut addr = None;
+    let mut id = 0;
+    let mut burst = 1;
+    let mut size = bus_bytes.ilog2() as u8;
+    let mut len = None;
+    let mut data = None;
+    let mut strb = None;
+    let mut expect = None;
+    let mut wuser = None;
+    let (mut cache, mut lock, mut prot, mut qos, mut region, mut user) = (0, 0, 0, 0, 0, 0);
+    for word in words {
+        let (key, value) = word
+            .split_once('=')
+            .ok_or_else(|| format!("expected `key=value`, got `{word}`"))?;
+        match key {
+            "addr" => addr = Some(parse_field(key, value)?),
+            "id" => id = parse_field(key, value)?,
+            "burst" => burst = parse_burst(value)?,
+            "size" => size = parse_field(key, value)?,
+            "len" => len = Some(parse_field(key, value)?),
+            "data" => data = Some(value),
+            "strb" => strb = Some(value),
+            "expect" => expect = Some(value),
+            "wuser" => wuser = Some(value),
+            "cache" => cache = parse_field(key, value)?,
+            "lock" => lock = parse_field(key, value)?,
+            "prot" => prot = parse_field(key, value)?,
+            "qos" => qos = parse_field(key, value)?,
+            "region" => region = parse_field(key, value)?,
+            "user" => user = parse_field(key, value)?,
+            _ => return Err(format!("unknown argument `{key}`")),
+        }
+    }
+    let addr = addr.ok_or("missing addr")?;
+    // AxSIZE has 3 bits
+    if size > 7 {
+        return Err(format!("size {size} is not encodable"));
+    }
+
+    let command = match op {
+        "write" => {
+            if len.is_some() {
+                return Err("write takes its length from the data beats, not len".to_owned());
+            }
+            let data = data
+                .ok_or("write needs data")?
+                .split(',')
+                .map(|beat| parse_beat(beat, bus_bytes))
+                .collect::<Result<Vec<_>, _>>()?;
+            if data.len() > layout.write_payload_size {
+                return Err(format!(
+                    "{} beats do not fit into a WritePayload of {} beats",
+                    data.len(),
+                    layout.write_payload_size
+                ));
+            }
+            let strb = match strb {
+                Some(strb) => strb
+                    .split(',')
+                    .map(|lanes| {
+                        u128::from_str_radix(lanes.strip_prefix("0x").unwrap_or(lanes), 16)
+                            .map_err(|e| format!("invalid strb `{lanes}`: {e}"))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+                None => {
+                    let len = data.len() as u8 - 1;
+                    burst_lanes(addr, burst, len, size, bus_bytes as u32)
+                        .iter()
+                        .map(BeatLanes::strobe)
+                        .collect()
+                }
+            };
+            let w_user = match wuser {
+                Some(wuser) => wuser
+                    .split(',')
+                    .map(|user| parse_number(&format!("0x{user}")).map(|user| user as u32))
+                    .collect::<Result<Vec<_>, _>>()?,
+                None => vec![0; data.len()],
+            };
+            if strb.len() != data.len() || w_user.len() != data.len() {
+                return Err("strb, wuser and data differ in beat count".to_owned());
+            }
+            Command::Write(AxiWritePayload {
+                id,
+                len: data.len() as u8 - 1,
+                addr,
+                data,
+                strb,
+                wUser: w_user,
+                awUser: user,
+                dataValid: true,
+                burst,
+                cache,
+                lock,
+                prot,
+                qos,
+                region,
+                size,
+            })
+        }
+        "read" => {
+            let len = len.unwrap_or(0);
+            let lanes = burst_lanes(addr, burst, len, size, bus_bytes as u32);
+            let expect = expect
+                .map(|expect| -> Result<Vec<u8>, String> {
+                    let beats = expect
+                        .split(',')
+                        .map(|beat| parse_beat(beat, bus_bytes))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    if beats.len() != len as usize + 1 {
+                        return Err(format!("expect has {} beats, len={len}", beats.len()));
+                    }
+                    // the value of each transfer, whichever lanes it uses
+                    Ok(beats
+                        .iter()
+                        .zip(&lanes)
+                        .flat_map(|(beat, lanes)| beat[..lanes.lanes.len()].iter().copied())
+                        .collect())
+                })
+                .transpose()?;
+            Command::Read {
+                payload: AxiReadPayload {
+                    addr,
+                    id,
+                    user,
+                    burst,
+                    cache,
+                    len,
+                    lock,
+                    prot,
+                    qos,
+                    region,
+                    size,
+                    valid: true,
+                },
+                expect,
+            }
+        }
+        _ => return Err(format!("unknown command `{op}`")),
+    };
+
+    if legal_only {
+        let violations = match &command {
+            Command::Write(payload) => protocol::check_write(payload, layout),
+            Command::Read { payload, .. } => protocol::check_read(payload, layout),
+            _ => Vec::new(),
+        };
+        if let Some(violation) = violations.first() {
+            return Err(violation.to_string());
+        }
+    }
+    Ok(Some(command))
+}
+
+/// Format little-endian beats the way `data` and `expect` take them.
+fn format_beats<'a>(beats: impl Iterator<Item = &'a [u8]>) -> String {
+    beats
+        .map(|beat| hex::encode(beat.iter().rev().copied().collect::<Vec<_>>()))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn format_hex<T: fmt::LowerHex>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(|value| format!("{value:x}"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Renders a command as a script line that parses back to the same command.
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Write(p) => write!(
+                f,
+                "write id={} addr={:#x} burst={} size={} data={} strb={} wuser={} \
+                 cache={} lock={} prot={} qos={} region={} user={:#x}",
+                p.id,
+                p.addr,
+                format_burst(p.burst),
+                p.size,
+                format_beats(p.data.iter().map(Vec::as_slice)),
+                format_hex(&p.strb),
+                format_hex(&p.wUser),
+                p.cache,
+                p.lock,
+                p.prot,
+                p.qos,
+                p.region,
+                p.awUser
+            ),
+            Command::Read { payload: p, expect } => {
+                write!(
+                    f,
+                    "read id={} addr={:#x} burst={} size={} len={} \
+                     cache={} lock={} prot={} qos={} region={} user={:#x}",
+                    p.id,
+                    p.addr,
+                    format_burst(p.burst),
+                    p.size,
+                    p.len,
+                    p.cache,
+                    p.lock,
+                    p.prot,
+                    p.qos,
+                    p.region,
+                    p.user
+                )?;
+                if let Some(expect) = expect {
+                    // bytes per beat do not depend on the bus width, the widest one will do
+                    let mut rest = expect.as_slice();
+                    let beats = burst_lanes(p.addr, p.burst, p.len, p.size, 128)
+                        .into_iter()
+                        .map(|beat| {
+                            let (transfer, tail) = rest.split_at(beat.lanes.len());
+                            rest = tail;
+                            transfer
+                        });
+                    write!(f, " expect={}", format_beats(beats))?;
+                }
+                Ok(())
+            }
+            Command::Wait(ticks) => write!(f, "wait {ticks}"),
+            Command::Barrier => write!(f, "barrier"),
+        }
+    }
+}
+
+pub(crate) fn parse(script: &str, layout: &PayloadLayout) -> Result<Vec<Command>, String> {
+    script
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            parse_command(line, layout, true)
+                .map_err(|e| format!("line {}: {e}", index + 1))
+                .transpose()
+        })
+        .collect()
+}
+
+/// Issues the commands of a script in order.
+pub(crate) struct ScriptGenerator {
+    commands: VecDeque<Command>,
+    wait_until: Option<u64>,
+    // `expect` of the issued reads, per ID in issue order
+    expects: Vec<(u8, Option<Vec<u8>>)>,
+}
+
+impl ScriptGenerator {
+    pub(crate) fn new(commands: Vec<Command>) -> Self {
+        ScriptGenerator {
+            commands: commands.into(),
+            wait_until: None,
+            expects: Vec::new(),
+        }
+    }
+
+    /// Retire waits and barriers that are over, returning the next transaction.
+    fn current(&mut self, ctx: &TrafficContext) -> Option<&Command> {
+        loop {
+            match self.commands.front()? {
+                Command::Wait(ticks) => {
+                    let until = *self.wait_until.get_or_insert(ctx.tick + ticks);
+                    if ctx.tick < until {
+                        return None;
+                    }
+                    self.wait_until = None;
+                }
+                Command::Barrier => {
+                    if ctx.outstanding > 0 {
+                        return None;
+                    }
+                }
+                _ => return self.commands.front(),
+            }
+            self.commands.pop_front();
+        }
+    }
+}
+
+impl TrafficGenerator for ScriptGenerator {
+    fn next_write(&mut self, ctx: &mut TrafficContext) -> Option<AxiWritePayload> {
+        let Command::Write(_) = self.current(ctx)? else {
+            return None;
+        };
+        let Some(Command::Write(payload)) = self.commands.pop_front() else {
+            unreachable!()
+        };
+        Some(payload)
+    }
+
+    fn next_read(&mut self, ctx: &mut TrafficContext) -> Option<AxiReadPayload> {
+        let Command::Read { .. } = self.current(ctx)? else {
+            return None;
+        };
+        let Some(Command::Read { payload, expect }) = self.commands.pop_front() else {
+            unreachable!()
+        };
+        self.expects.push((payload.id, expect));
+        Some(payload)
+    }
+
+    fn expected_read(&mut self, payload: &AxiReadPayload) -> Option<Vec<u8>> {
+        let index = self.expects.iter().position(|(id, _)| *id == payload.id)?;
+        self.expects.remove(index).1
+    }
+
+    fn finished(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dpi::test_layout;
+
+    #[test]
+    fn parse_script() {
+        let commands = parse(
+            "# fill bank 0\n\
+             write addr=0x1000 data=deadbeef,1 strb=f,3 id=2\n\
+             \n\
+             barrier\n\
+             read addr=0x1000 burst=wrap size=1 len=1 expect=beef,1 # check\n\
+             wait 0x10\n",
+            &PayloadLayout {
+                write_payload_size: 4,
+                ..test_layout()
+            },
+        )
+        .unwrap();
+        assert_eq!(commands.len(), 4);
+
+        let Command::Write(write) = &commands[0] else {
+            panic!("expected a write, got {:?}", commands[0]);
+        };
+        assert_eq!(
+            (write.id, write.len, write.addr, write.size),
+            (2, 1, 0x1000, 2)
+        );
+        assert_eq!(
+            write.data,
+            vec![vec![0xef, 0xbe, 0xad, 0xde], vec![1, 0, 0, 0]]
+        );
+        assert_eq!(write.strb, vec![0xf, 0x3]);
+
+        assert_eq!(commands[1], Command::Barrier);
+        let Command::Read { payload, expect } = &commands[2] else {
+            panic!("expected a read, got {:?}", commands[2]);
+        };
+        assert_eq!((payload.burst, payload.size, payload.len), (2, 1, 1));
+        assert_eq!(expect.as_deref(), Some(&[0xef, 0xbe, 0x01, 0x00][..]));
+        assert_eq!(commands[3], Command::Wait(16));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let layout = PayloadLayout {
+            write_payload_size: 4,
+            ..test_layout()
+        };
+        assert!(parse("write data=1", &layout)
+            .unwrap_err()
+            .contains("missing addr"));
+        assert!(parse("read addr=0 size=3", &layout).is_err());
+        assert!(parse("write addr=0 data=1,2,3,4,5", &layout).is_err());
+        assert!(parse("write addr=0 data=123456789a", &layout).is_err());
+        // fields are range checked before they are narrowed
+        assert_eq!(
+            parse("read addr=0 size=256", &layout).unwrap_err(),
+            "line 1: size=256 does not fit into its field"
+        );
+        assert!(parse("read addr=0 id=0x100", &layout).is_err());
+        assert!(parse("read addr=0x100000000", &layout).is_err());
+        assert!(parse("write addr=0 len=1 data=1,2", &layout)
+            .unwrap_err()
+            .contains("len"));
+        assert!(parse("\n\njump addr=0", &layout)
+            .unwrap_err()
+            .starts_with("line 3"));
+    }
+
+    #[test]
+    fn display_round_trip() {
+        let layout = PayloadLayout {
+            write_payload_size: 4,
+            ..test_layout()
+        };
+        let commands = parse(
+            "write id=3 addr=0x20 burst=fixed size=1 data=a5,5a0f strb=3,2 wuser=1,2 qos=4 user=0x7\n\
+             read id=1 addr=0x24 burst=wrap size=0 len=3 prot=2 expect=1,2,3,4\n\
+             wait 5\n\
+             barrier\n",
+            &layout,
+        )
+        .unwrap();
+        for command in commands {
+            let line = command.to_string();
+            assert_eq!(parse(&line, &layout).unwrap(), vec![command], "{line}");
+        }
+    }
+}
@@ -0,0 +1,636 @@
+//! Cycle-based model of an SDR SDRAM chip, clocked by `sdram_clock` on every
+//! rising edge of its clock so the testbench needs no vendor Verilog model.
+//!
+//! The model keeps the open row of every bank, and honours the CAS latency,
+//! burst length, burst type and write burst mode of the mode register as well
+//! as DQM, which masks write data in the same cycle and read data two cycles
+//! later. Timing parameters are not modelled, commands act on the edge they
+//! are registered at.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use common::sdram_part::SdramPart;
+
+/// The pins sampled at a rising clock edge.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Pins {
+    pub(crate) cke: bool,
+    pub(crate) cs_n: bool,
+    pub(crate) ras_n: bool,
+    pub(crate) cas_n: bool,
+    pub(crate) we_n: bool,
+    pub(crate) addr: u16,
+    pub(crate) bs: u8,
+    pub(crate) dqm: u8,
+    pub(crate) dq: u32,
+}
+
+const BIT_AUTO_PRECHARGE: u32 = 10;
+
+/// A command of the CS#, RAS#, CAS#, WE# truth table.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Command {
+    Deselect,
+    Nop,
+    Active {
+        bank: u8,
+        row: u32,
+    },
+    Read {
+        bank: u8,
+        column: u32,
+        auto_precharge: bool,
+    },
+    Write {
+        bank: u8,
+        column: u32,
+        auto_precharge: bool,
+    },
+    /// `None` precharges all banks
+    Precharge {
+        bank: Option<u8>,
+    },
+    Refresh,
+    LoadMode {
+        mode: u16,
+    },
+    BurstTerminate,
+}
+
+impl Command {
+    pub(crate) fn decode(pins: &Pins, part: &SdramPart) -> Self {
+        if pins.cs_n {
+            return Command::Deselect;
+        }
+        let bank = pins.bs & (part.banks - 1) as u8;
+        let column = pins.addr as u32 & (part.columns - 1);
+        let a10 = (pins.addr >> BIT_AUTO_PRECHARGE) & 1 != 0;
+        match (pins.ras_n, pins.cas_n, pins.we_n) {
+            (true, true, true) => Command::Nop,
+            (false, true, true) => Command::Active {
+                bank,
+                row: pins.addr as u32 & (part.rows - 1),
+            },
+            (true, false, true) => Command::Read {
+                bank,
+                column,
+                auto_precharge: a10,
+            },
+            (true, false, false) => Command::Write {
+                bank,
+                column,
+                auto_precharge: a10,
+            },
+            (false, true, false) => Command::Precharge {
+                bank: (!a10).then_some(bank),
+            },
+            (false, false, true) => Command::Refresh,
+            (false, false, false) => Command::LoadMode { mode: pins.addr },
+            (true, true, false) => Command::BurstTerminate,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Command::Deselect => "DESELECT",
+            Command::Nop => "NOP",
+            Command::Active { .. } => "ACTIVE",
+            Command::Read { .. } => "READ",
+            Command::Write { .. } => "WRITE",
+            Command::Precharge { .. } => "PRECHARGE",
+            Command::Refresh => "REFRESH",
+            Command::LoadMode { .. } => "LMR",
+            Command::BurstTerminate => "BST",
+        }
+    }
+}
+
+/// The command as it reads in a command log, e.g. `READ bank=1 col=0x40 AP=0`.
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())?;
+        match *self {
+            Command::Deselect | Command::Nop | Command::Refresh | Command::BurstTerminate => Ok(()),
+            Command::Active { bank, row } => write!(f, " bank={bank} row={row:#x}"),
+            Command::Read {
+                bank,
+                column,
+                auto_precharge,
+            }
+            | Command::Write {
+                bank,
+                column,
+                auto_precharge,
+            } => write!(
+                f,
+                " bank={bank} col={column:#x} AP={}",
+                auto_precharge as u8
+            ),
+            Command::Precharge { bank: Some(bank) } => write!(f, " bank={bank}"),
+            Command::Precharge { bank: None } => write!(f, " ALL"),
+            Command::LoadMode { mode } => match ModeRegister::decode(mode) {
+                Some(mode) => write!(f, " {mode}"),
+                None => write!(f, " {mode:#06x} reserved"),
+            },
+        }
+    }
+}
+
+/// The mode register as programmed by LOAD MODE REGISTER.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ModeRegister {
+    /// `None` for a full page burst
+    pub(crate) burst_length: Option<u32>,
+    pub(crate) interleaved: bool,
+    pub(crate) cas_latency: u32,
+    /// write bursts access a single location
+    pub(crate) single_write: bool,
+}
+
+impl ModeRegister {
+    /// `None` when a field holds a reserved value.
+    pub(crate) fn decode(mode: u16) -> Option<Self> {
+        let interleaved = (mode >> 3) & 1 != 0;
+        let burst_length = match mode & 0b111 {
+            length @ 0..=3 => Some(1 << length),
+            0b111 if !interleaved => None,
+            _ => return None,
+        };
+        let cas_latency = match (mode >> 4) & 0b111 {
+            latency @ 1..=3 => latency as u32,
+            _ => return None,
+        };
+        // only the standard operating mode is defined
+        if (mode >> 7) & 0b11 != 0 {
+            return None;
+        }
+        Some(ModeRegister {
+            burst_length,
+            interleaved,
+            cas_latency,
+            single_write: (mode >> 9) & 1 != 0,
+        })
+    }
+
+    /// Columns of a burst starting at `start`, in the order they are accessed.
+    fn burst_columns(&self, start: u32, columns: u32) -> Vec<u32> {
+        let Some(length) = self.burst_length else {
+            return (0..columns).map(|i| (start + i) % columns).collect();
+        };
+        let base = start & !(length - 1);
+        (0..length)
+            .map(|i| {
+                let offset = if self.interleaved {
+                    start ^ i
+                } else {
+                    start + i
+                };
+                base | (offset & (length - 1))
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for ModeRegister {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CL={} BL=", self.cas_latency)?;
+        match self.burst_length {
+            Some(length) => write!(f, "{length}")?,
+            None => write!(f, "page")?,
+        }
+        let burst_type = if self.interleaved { "int" } else { "seq" };
+        let write_burst = if self.single_write { "single" } else { "burst" };
+        write!(f, " BT={burst_type} WB={write_burst}")
+    }
+}
+
+/// A command the chip cannot execute in its current state.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum DeviceError {
+    /// READ or WRITE to a bank without an open row
+    BankIdle {
+        command: &'static str,
+        bank: u8,
+    },
+    /// ACTIVE to a bank whose row is still open
+    RowOpen {
+        bank: u8,
+        row: u32,
+    },
+    /// REFRESH or LOAD MODE REGISTER while a row is open
+    BanksActive {
+        command: &'static str,
+    },
+    ReservedMode {
+        mode: u16,
+    },
+    /// READ or WRITE before the mode register was loaded
+    ModeNotLoaded {
+        command: &'static str,
+    },
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceError::BankIdle { command, bank } => {
+                write!(f, "{command} to idle bank {bank}")
+            }
+            DeviceError::RowOpen { bank, row } => {
+                write!(f, "ACTIVE to bank {bank} with row {row:#x} still open")
+            }
+            DeviceError::BanksActive { command } => {
+                write!(f, "{command} while a bank is active")
+            }
+            DeviceError::ReservedMode { mode } => {
+                write!(f, "mode register {mode:#06x} holds a reserved value")
+            }
+            DeviceError::ModeNotLoaded { command } => {
+                write!(f, "{command} before LOAD MODE REGISTER")
+            }
+        }
+    }
+}
+
+/// A beat of a read burst, driven on DQ after the edge of `cycle`.
+struct ReadBeat {
+    cycle: u64,
+    bank: u8,
+    row: u32,
+    column: u32,
+    // the bank is precharged after the last beat
+    auto_precharge: bool,
+}
+
+/// The remaining columns of a write burst.
+struct WriteBurst {
+    bank: u8,
+    row: u32,
+    columns: VecDeque<u32>,
+    auto_precharge: bool,
+}
+
+pub(crate) struct SdramDevice {
+    part: SdramPart,
+
+    // rising edges seen so far
+    cycle: u64,
+
+    mode: Option<ModeRegister>,
+
+    open_rows: Vec<Option<u32>>,
+
+    // sparse cells by (bank, row, column)
+    cells: HashMap<u64, u32>,
+
+    // value of a cell that was never written
+    fill: u32,
+
+    reads: VecDeque<ReadBeat>,
+
+    write: Option<WriteBurst>,
+
+    // DQM of the previous edge, masking the read beat driven now
+    last_dqm: u8,
+
+    // value driven on DQ since the last edge
+    dq: u32,
+}
+
+impl SdramDevice {
+    pub(crate) fn new(part: SdramPart, fill: u8) -> Self {
+        let bytes = part.data_width / 8;
+        SdramDevice {
+            open_rows: vec![None; part.banks as usize],
+            fill: u32::from_le_bytes([fill; 4]) & Self::lanes_mask(bytes, 0),
+            part,
+            cycle: 0,
+            mode: None,
+            cells: HashMap::new(),
+            reads: VecDeque::new(),
+            write: None,
+            last_dqm: 0,
+            dq: 0,
+        }
+    }
+
+    /// Bits of the byte lanes not masked by `dqm`.
+    fn lanes_mask(bytes: u32, dqm: u8) -> u32 {
+        (0..bytes)
+            .filter(|lane| (dqm >> lane) & 1 == 0)
+            .fold(0, |mask, lane| mask | 0xff << (lane * 8))
+    }
+
+    fn cell(&self, bank: u8, row: u32, column: u32) -> u64 {
+        ((bank as u64 * self.part.rows as u64) + row as u64) * self.part.columns as u64
+            + column as u64
+    }
+
+    fn read_cell(&self, bank: u8, row: u32, column: u32) -> u32 {
+        let cell = self.cell(bank, row, column);
+        self.cells.get(&cell).copied().unwrap_or(self.fill)
+    }
+
+    fn write_cell(&mut self, bank: u8, row: u32, column: u32, value: u32, dqm: u8) {
+        let mask = Self::lanes_mask(self.part.data_width / 8, dqm);
+        let old = self.read_cell(bank, row, column);
+        let cell = self.cell(bank, row, column);
+        self.cells.insert(cell, (old & !mask) | (value & mask));
+    }
+
+    /// Registers the pins at a rising edge, returns the value driven on DQ
+    /// until the next one together with the commands that could not execute.
+    pub(crate) fn clock(&mut self, pins: &Pins) -> (u32, Vec<DeviceError>) {
+        let mut errors = Vec::new();
+        // CKE low suspends the clock
+        if !pins.cke {
+            return (self.dq, errors);
+        }
+        self.cycle += 1;
+        let read_dqm = std::mem::replace(&mut self.last_dqm, pins.dqm);
+        let command = Command::decode(pins, &self.part);
+        let mode = self.mode;
+        let cas_latency = mode.map_or(2, |mode| mode.cas_latency) as u64;
+
+        // a new burst, PRECHARGE or BURST TERMINATE cut the bursts in flight
+        let cut = self.cycle + cas_latency - 1;
+        match command {
+            Command::Read { .. } | Command::BurstTerminate => {
+                self.reads.retain(|beat| beat.cycle < cut);
+                self.write = None;
+            }
+            Command::Precharge { bank } => {
+                self.reads
+                    .retain(|beat| beat.cycle < cut || bank.is_some_and(|bank| bank != beat.bank));
+                if bank.is_none_or(|bank| self.write.as_ref().is_some_and(|w| w.bank == bank)) {
+                    self.write = None;
+                }
+            }
+            Command::Write { .. } => {
+                self.reads.clear();
+                self.write = None;
+            }
+            _ => {}
+        }
+
+        // the beats following the one registered with WRITE
+        if let Some(burst) = &mut self.write {
+            let (bank, row) = (burst.bank, burst.row);
+            let column = burst.columns.pop_front().unwrap();
+            let auto_precharge = burst.auto_precharge && burst.columns.is_empty();
+            if burst.columns.is_empty() {
+                self.write = None;
+            }
+            self.write_cell(bank, row, column, pins.dq, pins.dqm);
+            if auto_precharge {
+                self.open_rows[bank as usize] = None;
+            }
+        }
+
+        let any_open = self.open_rows.iter().any(Option::is_some);
+        match command {
+            Command::Deselect | Command::Nop | Command::BurstTerminate => {}
+            Command::Active { bank, row } => {
+                if let Some(open) = self.open_rows[bank as usize] {
+                    errors.push(DeviceError::RowOpen { bank, row: open });
+                }
+                self.open_rows[bank as usize] = Some(row);
+            }
+            Command::Read {
+                bank,
+                column,
+                auto_precharge,
+            }
+            | Command::Write {
+                bank,
+                column,
+                auto_precharge,
+            } => {
+                let Some(mode) = mode else {
+                    errors.push(DeviceError::ModeNotLoaded {
+                        command: command.name(),
+                    });
+                    return (self.drive(read_dqm), errors);
+                };
+                let Some(row) = self.open_rows[bank as usize] else {
+                    errors.push(DeviceError::BankIdle {
+                        command: command.name(),
+                        bank,
+                    });
+                    return (self.drive(read_dqm), errors);
+                };
+                let mut columns = mode.burst_columns(column, self.part.columns);
+                if let Command::Read { .. } = command {
+                    let last = columns.len() - 1;
+                    for (index, column) in columns.into_iter().enumerate() {
+                        self.reads.push_back(ReadBeat {
+                            cycle: cut + index as u64,
+                            bank,
+                            row,
+                            column,
+                            auto_precharge: auto_precharge && index == last,
+                        });
+                    }
+                } else {
+                    if mode.single_write {
+                        columns.truncate(1);
+                    }
+                    let mut columns = VecDeque::from(columns);
+                    self.write_cell(bank, row, columns.pop_front().unwrap(), pins.dq, pins.dqm);
+                    if !columns.is_empty() {
+                        self.write = Some(WriteBurst {
+                            bank,
+                            row,
+                            columns,
+                            auto_precharge,
+                        });
+                    } else if auto_precharge {
+                        self.open_rows[bank as usize] = None;
+                    }
+                }
+            }
+            Command::Precharge { bank: Some(bank) } => self.open_rows[bank as usize] = None,
+            Command::Precharge { bank: None } => self.open_rows.fill(None),
+            Command::Refresh => {
+                if any_open {
+                    errors.push(DeviceError::BanksActive {
+                        command: command.name(),
+                    });
+                }
+            }
+            Command::LoadMode { mode } => {
+                if any_open {
+                    errors.push(DeviceError::BanksActive {
+                        command: command.name(),
+                    });
+                }
+                match ModeRegister::decode(mode) {
+                    Some(mode) => self.mode = Some(mode),
+                    None => errors.push(DeviceError::ReservedMode { mode }),
+                }
+            }
+        }
+        (self.drive(read_dqm), errors)
+    }
+
+    /// Drive the read beat due after this edge, masked by `dqm`.
+    fn drive(&mut self, dqm: u8) -> u32 {
+        self.dq = 0;
+        while let Some(beat) = self.reads.front() {
+            if beat.cycle > self.cycle {
+                break;
+            }
+            let beat = self.reads.pop_front().unwrap();
+            if beat.cycle < self.cycle {
+                continue;
+            }
+            let mask = Self::lanes_mask(self.part.data_width / 8, dqm);
+            self.dq = self.read_cell(beat.bank, beat.row, beat.column) & mask;
+            if beat.auto_precharge {
+                self.open_rows[beat.bank as usize] = None;
+            }
+        }
+        self.dq
+    }
+
+    pub(crate) fn mode(&self) -> Option<ModeRegister> {
+        self.mode
+    }
+
+    pub(crate) fn part(&self) -> &SdramPart {
+        &self.part
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(cs_n: bool, ras_n: bool, cas_n: bool, we_n: bool, addr: u16, bs: u8) -> Pins {
+        Pins {
+            cke: true,
+            cs_n,
+            ras_n,
+            cas_n,
+            we_n,
+            addr,
+            bs,
+            ..Pins::default()
+        }
+    }
+
+    fn nop() -> Pins {
+        command(false, true, true, true, 0, 0)
+    }
+
+    #[test]
+    fn burst_order() {
+        let mode = |bits| ModeRegister::decode(bits).unwrap();
+        // BL4 sequential and interleaved, starting at column 6
+        assert_eq!(mode(0b010_0010).burst_columns(6, 512), vec![6, 7, 4, 5]);
+        assert_eq!(mode(0b010_1010).burst_columns(6, 512), vec![6, 7, 4, 5]);
+        assert_eq!(mode(0b010_1010).burst_columns(5, 512), vec![5, 4, 7, 6]);
+        assert_eq!(mode(0b010_0111).burst_columns(510, 512)[..3], [510, 511, 0]);
+        assert_eq!(ModeRegister::decode(0b010_1111), None);
+        assert_eq!(ModeRegister::decode(0b000_0001), None);
+        assert_eq!(ModeRegister::decode(0b1_0010_0001), None);
+    }
+
+    #[test]
+    fn write_then_read_with_latency() {
+        let mut device = SdramDevice::new(SdramPart::default(), 0);
+        let mut clock = |pins: Pins| {
+            let (dq, errors) = device.clock(&pins);
+            assert_eq!(errors, vec![]);
+            dq
+        };
+        // CL2, sequential BL2, as programmed by the controller
+        clock(command(false, false, false, false, 0b010_0001, 0));
+        clock(command(false, false, true, true, 0x1a3, 1));
+        clock(Pins {
+            dq: 0xbeef,
+            dqm: 0b10,
+            ..command(false, true, false, false, 0x40, 1)
+        });
+        clock(Pins {
+            dq: 0xcafe,
+            ..nop()
+        });
+        clock(command(false, true, false, true, 0x40, 1));
+        assert_eq!(clock(nop()), 0x00ef);
+        assert_eq!(clock(nop()), 0xcafe);
+        // DQM masks the beat two edges later
+        clock(Pins {
+            dqm: 0b01,
+            ..command(false, true, false, true, 0x41, 1)
+        });
+        assert_eq!(clock(nop()), 0xca00);
+        assert_eq!(clock(nop()), 0x00ef);
+        assert_eq!(clock(nop()), 0);
+    }
+
+    #[test]
+    fn commands_to_the_wrong_state_fail() {
+        let mut device = SdramDevice::new(SdramPart::default(), 0);
+        let read = command(false, true, false, true, 0, 2);
+        assert_eq!(
+            device.clock(&read).1,
+            vec![DeviceError::ModeNotLoaded { command: "READ" }]
+        );
+        device.clock(&command(false, false, false, false, 0b011_0000, 0));
+        assert_eq!(device.mode.unwrap().cas_latency, 3);
+        assert_eq!(
+            device.clock(&read).1,
+            vec![DeviceError::BankIdle {
+                command: "READ",
+                bank: 2
+            }]
+        );
+        device.clock(&command(false, false, true, true, 7, 2));
+        assert_eq!(
+            device.clock(&command(false, false, true, true, 8, 2)).1,
+            vec![DeviceError::RowOpen { bank: 2, row: 7 }]
+        );
+        assert_eq!(
+            device.clock(&command(false, false, false, true, 0, 0)).1,
+            vec![DeviceError::BanksActive { command: "REFRESH" }]
+        );
+        // PRECHARGE with A10 closes every bank
+        device.clock(&command(false, false, true, false, 1 << 10, 0));
+        assert_eq!(
+            device.clock(&command(false, false, false, true, 0, 0)).1,
+            vec![]
+        );
+    }
+
+    #[test]
+    fn command_log_lines() {
+        let part = SdramPart::default();
+        let decode = |pins: Pins| Command::decode(&pins, &part).to_string();
+        assert_eq!(
+            decode(command(false, false, true, true, 0x1a3, 1)),
+            "ACTIVE bank=1 row=0x1a3"
+        );
+        assert_eq!(
+            decode(command(false, true, false, true, 0x40, 1)),
+            "READ bank=1 col=0x40 AP=0"
+        );
+        assert_eq!(
+            decode(command(false, true, false, false, 1 << 10 | 0x3, 0)),
+            "WRITE bank=0 col=0x3 AP=1"
+        );
+        assert_eq!(
+            decode(command(false, false, true, false, 1 << 10, 0)),
+            "PRECHARGE ALL"
+        );
+        assert_eq!(
+            decode(command(false, false, false, false, 0b010_0001, 0)),
+            "LMR CL=2 BL=2 BT=seq WB=burst"
+        );
+        assert_eq!(
+            decode(command(false, false, false, false, 0b000_0001, 0)),
+            "LMR 0x0001 reserved"
+        );
+    }
+}
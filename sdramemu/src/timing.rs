@@ -0,0 +1,306 @@
+//! JEDEC timing of the command stream of one SDRAM chip, checked against the
+//! timing table of its part.
+
+use std::fmt;
+
+use crate::sdram::{Command, ModeRegister};
+use common::sdram_part::{SdramPart, SdramTiming, TimingParameter};
+
+/// A command issued too early, or a mode the part cannot run at its clock.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum TimingViolation {
+    Delay {
+        parameter: &'static str,
+        command: &'static str,
+        bank: Option<u8>,
+        required: u64,
+        elapsed: u64,
+    },
+    CasLatency {
+        cas_latency: u32,
+        clock_mhz: f64,
+    },
+}
+
+impl fmt::Display for TimingViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimingViolation::Delay {
+                parameter,
+                command,
+                bank,
+                required,
+                elapsed,
+            } => {
+                write!(f, "{command}")?;
+                if let Some(bank) = bank {
+                    write!(f, " bank {bank}")?;
+                }
+                write!(
+                    f,
+                    " violates {parameter}: {elapsed} cycle(s), {required} required"
+                )
+            }
+            TimingViolation::CasLatency {
+                cas_latency,
+                clock_mhz,
+            } => write!(
+                f,
+                "CAS latency {cas_latency} is not supported at {clock_mhz} MHz"
+            ),
+        }
+    }
+}
+
+/// The minimum delays of the timing table, in clock cycles.
+struct Cycles {
+    rcd: u64,
+    rp: u64,
+    ras: u64,
+    rc: u64,
+    rrd: u64,
+    wr: u64,
+    rfc: u64,
+    mrd: u64,
+}
+
+#[derive(Clone, Default)]
+struct BankTiming {
+    // ACTIVE of the open row
+    active: Option<u64>,
+    // the latest ACTIVE, for tRC
+    last_active: Option<u64>,
+    // start of the latest precharge, for tRP
+    precharge: Option<u64>,
+    // cycle of the latest write data, for tWR
+    write_end: Option<u64>,
+}
+
+pub(crate) struct TimingChecker {
+    timing: SdramTiming,
+
+    cycles: Cycles,
+
+    columns: u32,
+
+    // clock edges seen so far
+    cycle: u64,
+
+    banks: Vec<BankTiming>,
+
+    // the latest ACTIVE of any bank, for tRRD
+    last_active: Option<(u64, u8)>,
+
+    refresh: Option<u64>,
+
+    load_mode: Option<u64>,
+}
+
+impl TimingChecker {
+    pub(crate) fn new(part: &SdramPart) -> Self {
+        let timing = part.timing.clone();
+        let cycles = |parameter: TimingParameter| parameter.cycles(timing.clock_mhz);
+        TimingChecker {
+            cycles: Cycles {
+                rcd: cycles(timing.t_rcd),
+                rp: cycles(timing.t_rp),
+                ras: cycles(timing.t_ras),
+                rc: cycles(timing.t_rc),
+                rrd: cycles(timing.t_rrd),
+                wr: cycles(timing.t_wr),
+                rfc: cycles(timing.t_rfc),
+                mrd: cycles(timing.t_mrd),
+            },
+            timing,
+            columns: part.columns,
+            cycle: 0,
+            banks: vec![BankTiming::default(); part.banks as usize],
+            last_active: None,
+            refresh: None,
+            load_mode: None,
+        }
+    }
+
+    /// Check the command registered at the next clock edge, `mode` is the mode
+    /// register in effect.
+    pub(crate) fn clock(
+        &mut self,
+        command: &Command,
+        mode: Option<ModeRegister>,
+    ) -> Vec<TimingViolation> {
+        self.cycle += 1;
+        let cycle = self.cycle;
+        let mut violations = Vec::new();
+        let mut check = |parameter, bank, since: Option<u64>, required| {
+            let Some(since) = since else {
+                return;
+            };
+            let elapsed = cycle.saturating_sub(since);
+            if elapsed < required {
+                violations.push(TimingViolation::Delay {
+                    parameter,
+                    command: command.name(),
+                    bank,
+                    required,
+                    elapsed,
+                });
+            }
+        };
+        if matches!(command, Command::Deselect | Command::Nop) {
+            return violations;
+        }
+        check("tRFC", None, self.refresh, self.cycles.rfc);
+        check("tMRD", None, self.load_mode, self.cycles.mrd);
+
+        // a new burst or precharge cuts a write burst short
+        if matches!(
+            command,
+            Command::Read { .. }
+                | Command::Write { .. }
+                | Command::Precharge { .. }
+                | Command::BurstTerminate
+        ) {
+            for bank in &mut self.banks {
+                bank.write_end = bank.write_end.map(|end| end.min(cycle - 1));
+            }
+        }
+
+        let burst_length = |mode: Option<ModeRegister>| {
+            mode.map_or(1, |mode| mode.burst_length.unwrap_or(self.columns)) as u64
+        };
+        let latest_precharge = self.banks.iter().filter_map(|bank| bank.precharge).max();
+        match *command {
+            Command::Deselect | Command::Nop | Command::BurstTerminate => {}
+            Command::Active { bank, .. } => {
+                let timing = &mut self.banks[bank as usize];
+                check("tRP", Some(bank), timing.precharge, self.cycles.rp);
+                check("tRC", Some(bank), timing.last_active, self.cycles.rc);
+                // tRC covers the same bank
+                let other_bank = self.last_active.filter(|(_, other)| *other != bank);
+                check(
+                    "tRRD",
+                    Some(bank),
+                    other_bank.map(|(since, _)| since),
+                    self.cycles.rrd,
+                );
+                timing.active = Some(cycle);
+                timing.last_active = Some(cycle);
+                self.last_active = Some((cycle, bank));
+            }
+            Command::Read {
+                bank,
+                auto_precharge,
+                ..
+            } => {
+                let timing = &mut self.banks[bank as usize];
+                check("tRCD", Some(bank), timing.active, self.cycles.rcd);
+                if auto_precharge && timing.active.is_some() {
+                    timing.active = None;
+                    // auto precharge starts after the burst
+                    timing.precharge = Some(cycle + burst_length(mode));
+                }
+            }
+            Command::Write {
+                bank,
+                auto_precharge,
+                ..
+            } => {
+                let timing = &mut self.banks[bank as usize];
+                check("tRCD", Some(bank), timing.active, self.cycles.rcd);
+                let length = match mode {
+                    Some(mode) if mode.single_write => 1,
+                    _ => burst_length(mode),
+                };
+                let write_end = cycle + length - 1;
+                timing.write_end = Some(write_end);
+                if auto_precharge && timing.active.is_some() {
+                    timing.active = None;
+                    timing.precharge = Some(write_end + self.cycles.wr);
+                }
+            }
+            Command::Precharge { bank } => {
+                for (index, timing) in self.banks.iter_mut().enumerate() {
+                    let index = index as u8;
+                    // precharging an idle bank is a NOP
+                    if bank.is_some_and(|bank| bank != index) || timing.active.is_none() {
+                        continue;
+                    }
+                    check("tRAS", Some(index), timing.active, self.cycles.ras);
+                    check("tWR", Some(index), timing.write_end, self.cycles.wr);
+                    timing.active = None;
+                    timing.precharge = Some(cycle);
+                }
+            }
+            Command::Refresh => {
+                check("tRP", None, latest_precharge, self.cycles.rp);
+                self.refresh = Some(cycle);
+            }
+            Command::LoadMode { mode } => {
+                check("tRP", None, latest_precharge, self.cycles.rp);
+                self.load_mode = Some(cycle);
+                if let Some(mode) = ModeRegister::decode(mode) {
+                    if !self.timing.supports_cas_latency(mode.cas_latency) {
+                        violations.push(TimingViolation::CasLatency {
+                            cas_latency: mode.cas_latency,
+                            clock_mhz: self.timing.clock_mhz,
+                        });
+                    }
+                }
+            }
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_between_commands() {
+        let mut part = SdramPart::default();
+        // longer than tRAS + tRP, so that it can be violated on its own
+        part.timing.t_rc = TimingParameter {
+            ns: 200.0,
+            clocks: 0,
+        };
+        let mut checker = TimingChecker::new(&part);
+        let mode = ModeRegister::decode(0b010_0001);
+        let mut clock = |command: Command| -> Vec<&'static str> {
+            checker
+                .clock(&command, mode)
+                .into_iter()
+                .map(|violation| match violation {
+                    TimingViolation::Delay { parameter, .. } => parameter,
+                    TimingViolation::CasLatency { .. } => "CL",
+                })
+                .collect()
+        };
+        let active = |bank| Command::Active { bank, row: 0 };
+        let write = Command::Write {
+            bank: 0,
+            column: 0,
+            auto_precharge: false,
+        };
+        let precharge = |bank| Command::Precharge { bank };
+        // 20 ns cycles: tRCD 1, tRP 1, tRAS 3, tRC 10, tRRD 2, tWR 2, tRFC 3, tMRD 2
+        assert_eq!(
+            clock(Command::LoadMode { mode: 0b010_0001 }),
+            Vec::<&str>::new()
+        );
+        assert_eq!(clock(active(0)), vec!["tMRD"]);
+        assert_eq!(clock(active(1)), vec!["tRRD"]);
+        assert_eq!(clock(write), Vec::<&str>::new());
+        assert_eq!(clock(Command::Nop), Vec::<&str>::new());
+        // BL2 write data ends on the NOP
+        assert_eq!(clock(precharge(Some(0))), vec!["tWR"]);
+        assert_eq!(clock(active(0)), vec!["tRC"]);
+        assert_eq!(clock(precharge(None)), vec!["tRAS"]);
+        assert_eq!(clock(Command::Refresh), Vec::<&str>::new());
+        assert_eq!(clock(Command::Refresh), vec!["tRFC"]);
+        assert_eq!(
+            clock(Command::LoadMode { mode: 0b001_0000 }),
+            vec!["tRFC", "CL"]
+        );
+    }
+}
@@ -0,0 +1,455 @@
+use common::constraints::Constraints;
+use common::memory_map::MemoryMap;
+use rand::rngs::StdRng;
+use rand::Rng;
+use std::collections::VecDeque;
+use std::ops::Range;
+
+use crate::dpi::{burst_lanes, AxiReadPayload, AxiWritePayload, PayloadLayout};
+
+/// What a [`TrafficGenerator`] may use when asked for the next transaction.
+pub(crate) struct TrafficContext<'a> {
+    pub(crate) layout: &'a PayloadLayout,
+    // the Driver's seeded RNG, generators must not use any other randomness
+    pub(crate) rng: &'a mut StdRng,
+    pub(crate) tick: u64,
+    // transactions issued but not yet responded to
+    pub(crate) outstanding: usize,
+    pub(crate) in_flight: &'a InFlight<'a>,
+}
+
+/// Chip bytes touched by the transactions in flight.
+///
+/// AXI orders neither transactions of different IDs nor reads against writes,
+/// so the shadow memory only predicts a read that overlaps no write in flight,
+/// and a write that overlaps nothing in flight.
+pub(crate) struct InFlight<'a> {
+    map: &'a MemoryMap,
+    bus_bytes: u32,
+    writes: Vec<Range<u64>>,
+    reads: Vec<Range<u64>>,
+}
+
+impl<'a> InFlight<'a> {
+    pub(crate) fn new(map: &'a MemoryMap, bus_bytes: u32) -> Self {
+        InFlight {
+            map,
+            bus_bytes,
+            writes: Vec::new(),
+            reads: Vec::new(),
+        }
+    }
+
+    /// Chip bytes of a burst, `None` when it is dropped as out of range.
+    fn span(&self, addr: u32, burst: u8, len: u8, size: u8) -> Option<Range<u64>> {
+        let lanes = burst_lanes(addr, burst, len, size, self.bus_bytes);
+        let start = lanes
+            .iter()
+            .map(|beat| beat.base as u64 + beat.lanes.start as u64)
+            .min()?;
+        let end = lanes
+            .iter()
+            .map(|beat| beat.base as u64 + beat.lanes.end as u64)
+            .max()?;
+        // a burst stays within 4 KiB, which aliases as a whole
+        let chip_start = self.map.translate(start)?;
+        Some(chip_start..chip_start + (end - start))
+    }
+
+    fn write_span(&self, payload: &AxiWritePayload) -> Option<Range<u64>> {
+        self.span(payload.addr, payload.burst, payload.len, payload.size)
+    }
+
+    fn read_span(&self, payload: &AxiReadPayload) -> Option<Range<u64>> {
+        self.span(payload.addr, payload.burst, payload.len, payload.size)
+    }
+
+    pub(crate) fn add_write(&mut self, payload: &AxiWritePayload) {
+        self.writes.extend(self.write_span(payload));
+    }
+
+    pub(crate) fn add_read(&mut self, payload: &AxiReadPayload) {
+        self.reads.extend(self.read_span(payload));
+    }
+
+    /// Whether `payload` may land before or after a transaction in flight.
+    pub(crate) fn blocks_write(&self, payload: &AxiWritePayload) -> bool {
+        self.write_span(payload)
+            .is_some_and(|span| overlap(&span, &self.writes) || overlap(&span, &self.reads))
+    }
+
+    /// Whether `payload` may be served before or after a write in flight.
+    pub(crate) fn blocks_read(&self, payload: &AxiReadPayload) -> bool {
+        self.read_span(payload)
+            .is_some_and(|span| overlap(&span, &self.writes))
+    }
+}
+
+fn overlap(span: &Range<u64>, spans: &[Range<u64>]) -> bool {
+    spans
+        .iter()
+        .any(|other| span.start < other.end && other.start < span.end)
+}
+
+/// Source of AXI stimulus, polled by the Driver whenever the agent can take a
+/// new AW or AR payload.
+pub(crate) trait TrafficGenerator: Send {
+    /// Next write burst, or `None` to issue nothing this cycle.
+    fn next_write(&mut self, ctx: &mut TrafficContext) -> Option<AxiWritePayload>;
+
+    /// Next read burst, or `None` to issue nothing this cycle.
+    fn next_read(&mut self, ctx: &mut TrafficContext) -> Option<AxiReadPayload>;
+
+    /// A write burst completed with an OKAY response.
+    fn write_done(&mut self, _payload: &AxiWritePayload) {}
+
+    /// A read burst completed.
+    fn read_done(&mut self, _payload: &AxiReadPayload) {}
+
+    /// Data the generator expects `payload` to return, checked in addition to
+    /// the shadow memory. Called once per completed read.
+    fn expected_read(&mut self, _payload: &AxiReadPayload) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// No more transactions will be issued, the run may finish.
+    fn finished(&self) -> bool {
+        false
+    }
+}
+
+/// Next AXI id, wrapping within idWidth as echoed back on B and R.
+fn next_id(counter: &mut u8, layout: &PayloadLayout) -> u8 {
+    let id_mask = (1u32 << layout.id_width.min(8)) - 1;
+    *counter = counter.wrapping_add(1);
+    (*counter as u32 & id_mask) as u8
+}
+
+fn below_max_outstanding(constraints: &Constraints, ctx: &TrafficContext) -> bool {
+    constraints
+        .max_outstanding
+        .is_none_or(|max| ctx.outstanding < max)
+}
+
+/// Random writes, each read back once its B response arrived.
+pub(crate) struct WriteReadback {
+    constraints: Constraints,
+    next_awid: u8,
+    done: VecDeque<AxiWritePayload>,
+}
+
+impl WriteReadback {
+    pub(crate) fn new(constraints: Constraints) -> Self {
+        WriteReadback {
+            constraints,
+            next_awid: 0,
+            done: VecDeque::new(),
+        }
+    }
+}
+
+impl TrafficGenerator for WriteReadback {
+    fn next_write(&mut self, ctx: &mut TrafficContext) -> Option<AxiWritePayload> {
+        if !below_max_outstanding(&self.constraints, ctx) {
+            return None;
+        }
+        let id = next_id(&mut self.next_awid, ctx.layout);
+        let payload = AxiWritePayload::random(ctx.layout, &self.constraints, ctx.rng, id);
+        (!ctx.in_flight.blocks_write(&payload)).then_some(payload)
+    }
+
+    fn next_read(&mut self, ctx: &mut TrafficContext) -> Option<AxiReadPayload> {
+        if !below_max_outstanding(&self.constraints, ctx) {
+            return None;
+        }
+        // a later write over the same bytes holds the read back until its B
+        let payload = AxiReadPayload::from_write_payload(self.done.front()?.clone());
+        if ctx.in_flight.blocks_read(&payload) {
+            return None;
+        }
+        self.done.pop_front();
+        Some(payload)
+    }
+
+    fn write_done(&mut self, payload: &AxiWritePayload) {
+        self.done.push_back(payload.clone());
+    }
+}
+
+// completed writes kept around as read targets
+const READ_TARGETS: usize = 64;
+
+/// Random writes and reads mixed by `readWriteRatio`, one transaction per
+/// tick. Reads revisit random completed writes, so they hit initialized memory.
+pub(crate) struct MixedRandom {
+    constraints: Constraints,
+    next_id: u8,
+    written: Vec<AxiWritePayload>,
+    // kind picked for a tick, read or write, `None` once it was issued
+    pick: Option<(u64, Option<bool>)>,
+}
+
+impl MixedRandom {
+    pub(crate) fn new(constraints: Constraints) -> Self {
+        MixedRandom {
+            constraints,
+            next_id: 0,
+            written: Vec::new(),
+            pick: None,
+        }
+    }
+
+    /// Whether this tick is due for a read (`true`) or a write (`false`).
+    fn pick(&mut self, ctx: &mut TrafficContext, read: bool) -> bool {
+        if !below_max_outstanding(&self.constraints, ctx) {
+            return false;
+        }
+        let picked = match self.pick {
+            Some((tick, picked)) if tick == ctx.tick => picked,
+            _ => {
+                let ratio = &self.constraints.read_write_ratio;
+                Some(
+                    !self.written.is_empty()
+                        && ctx.rng.gen_ratio(ratio.read, ratio.read + ratio.write),
+                )
+            }
+        };
+        if picked == Some(read) {
+            self.pick = Some((ctx.tick, None));
+            return true;
+        }
+        self.pick = Some((ctx.tick, picked));
+        false
+    }
+}
+
+impl TrafficGenerator for MixedRandom {
+    fn next_write(&mut self, ctx: &mut TrafficContext) -> Option<AxiWritePayload> {
+        if !self.pick(ctx, false) {
+            return None;
+        }
+        let id = next_id(&mut self.next_id, ctx.layout);
+        let payload = AxiWritePayload::random(ctx.layout, &self.constraints, ctx.rng, id);
+        (!ctx.in_flight.blocks_write(&payload)).then_some(payload)
+    }
+
+    fn next_read(&mut self, ctx: &mut TrafficContext) -> Option<AxiReadPayload> {
+        if !self.pick(ctx, true) {
+            return None;
+        }
+        let target = self.written[ctx.rng.gen_range(0..self.written.len())].clone();
+        let mut payload = AxiReadPayload::from_write_payload(target);
+        payload.id = next_id(&mut self.next_id, ctx.layout);
+        (!ctx.in_flight.blocks_read(&payload)).then_some(payload)
+    }
+
+    fn write_done(&mut self, payload: &AxiWritePayload) {
+        if self.written.len() < READ_TARGETS {
+            self.written.push(payload.clone());
+        } else {
+            let index = payload.addr as usize % READ_TARGETS;
+            self.written[index] = payload.clone();
+        }
+    }
+}
+
+/// Names accepted by `--traffic`.
+pub(crate) const GENERATORS: &[&str] = &["readback", "mixed"];
+
+pub(crate) fn by_name(name: &str, constraints: Constraints) -> Option<Box<dyn TrafficGenerator>> {
+    match name {
+        "readback" => Some(Box::new(WriteReadback::new(constraints))),
+        "mixed" => Some(Box::new(MixedRandom::new(constraints))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dpi::test_layout;
+    use common::constraints::{BurstWeights, InclusiveRange};
+    use rand::SeedableRng;
+
+    #[test]
+    fn readback_reads_completed_writes() {
+        let layout = test_layout();
+        let map = MemoryMap::default();
+        let in_flight = InFlight::new(&map, 4);
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut ctx = TrafficContext {
+            layout: &layout,
+            rng: &mut rng,
+            tick: 0,
+            outstanding: 0,
+            in_flight: &in_flight,
+        };
+        let mut generator = by_name("readback", Constraints::default()).unwrap();
+
+        assert!(generator.next_read(&mut ctx).is_none());
+        let write = generator.next_write(&mut ctx).unwrap();
+        assert!(generator.next_read(&mut ctx).is_none());
+        generator.write_done(&write);
+        let read = generator.next_read(&mut ctx).unwrap();
+        assert_eq!(
+            (read.id, read.addr, read.len),
+            (write.id, write.addr, write.len)
+        );
+        assert!(generator.next_read(&mut ctx).is_none());
+    }
+
+    #[test]
+    fn readback_follows_completion_order() {
+        let layout = PayloadLayout {
+            id_width: 2,
+            ..test_layout()
+        };
+        let map = MemoryMap::default();
+        let in_flight = InFlight::new(&map, 4);
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut ctx = TrafficContext {
+            layout: &layout,
+            rng: &mut rng,
+            tick: 0,
+            outstanding: 0,
+            in_flight: &in_flight,
+        };
+        let mut generator = WriteReadback::new(Constraints::default());
+
+        let writes: Vec<_> = (0..6)
+            .map(|_| generator.next_write(&mut ctx).unwrap())
+            .collect();
+        // ids wrap within idWidth
+        let ids: Vec<u8> = writes.iter().map(|write| write.id).collect();
+        assert_eq!(ids, [1, 2, 3, 0, 1, 2]);
+
+        // B responses of different ids may come back in any order
+        for index in [2, 0, 5] {
+            generator.write_done(&writes[index]);
+        }
+        for index in [2, 0, 5] {
+            let read = generator.next_read(&mut ctx).unwrap();
+            assert_eq!(
+                (read.id, read.addr, read.len, read.burst),
+                (
+                    writes[index].id,
+                    writes[index].addr,
+                    writes[index].len,
+                    writes[index].burst
+                )
+            );
+        }
+        assert!(generator.next_read(&mut ctx).is_none());
+    }
+
+    #[test]
+    fn readback_stops_at_max_outstanding() {
+        let layout = test_layout();
+        let constraints = Constraints {
+            max_outstanding: Some(2),
+            ..Constraints::default()
+        };
+        let map = MemoryMap::default();
+        let in_flight = InFlight::new(&map, 4);
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut generator = WriteReadback::new(constraints);
+        let mut ctx = TrafficContext {
+            layout: &layout,
+            rng: &mut rng,
+            tick: 0,
+            outstanding: 1,
+            in_flight: &in_flight,
+        };
+
+        let write = generator.next_write(&mut ctx).unwrap();
+        generator.write_done(&write);
+        ctx.outstanding = 2;
+        assert!(generator.next_write(&mut ctx).is_none());
+        assert!(generator.next_read(&mut ctx).is_none());
+        // the read back was only held, not dropped
+        ctx.outstanding = 1;
+        assert_eq!(generator.next_read(&mut ctx).unwrap().addr, write.addr);
+    }
+
+    #[test]
+    fn in_flight_overlaps_through_aliases() {
+        let map = MemoryMap::default();
+        let mut in_flight = InFlight::new(&map, 4);
+        let write = |addr| AxiWritePayload {
+            addr,
+            ..AxiWritePayload::random(
+                &PayloadLayout {
+                    write_payload_size: 2,
+                    ..test_layout()
+                },
+                &Constraints {
+                    burst_weights: BurstWeights {
+                        fixed: 0,
+                        incr: 1,
+                        wrap: 0,
+                    },
+                    beats: InclusiveRange { min: 2, max: 2 },
+                    size: InclusiveRange { min: 2, max: 2 },
+                    ..Constraints::default()
+                },
+                &mut StdRng::seed_from_u64(0),
+                0,
+            )
+        };
+        // 0x40..0x48 in flight
+        in_flight.add_write(&write(0x40));
+        in_flight.add_read(&AxiReadPayload::from_write_payload(write(0x100)));
+
+        let read = |addr| AxiReadPayload::from_write_payload(write(addr));
+        assert!(in_flight.blocks_read(&read(0x44)));
+        // the upper address bits are not decoded
+        assert!(in_flight.blocks_read(&read(0x0200_0040)));
+        assert!(!in_flight.blocks_read(&read(0x48)));
+        // reads in flight only hold back writes
+        assert!(!in_flight.blocks_read(&read(0x100)));
+        assert!(in_flight.blocks_write(&write(0x104)));
+        assert!(!in_flight.blocks_write(&write(0x38)));
+    }
+
+    #[test]
+    fn mixed_issues_one_transaction_per_tick() {
+        let layout = test_layout();
+        let constraints = Constraints {
+            max_outstanding: Some(4),
+            ..Constraints::default()
+        };
+        let map = MemoryMap::default();
+        let in_flight = InFlight::new(&map, 4);
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut generator = by_name("mixed", constraints).unwrap();
+        let (mut writes, mut reads) = (0, 0);
+        for tick in 0..1000 {
+            let mut ctx = TrafficContext {
+                layout: &layout,
+                rng: &mut rng,
+                tick,
+                outstanding: 0,
+                in_flight: &in_flight,
+            };
+            let write = generator.next_write(&mut ctx);
+            let read = generator.next_read(&mut ctx);
+            assert!(write.is_none() || read.is_none());
+            if let Some(write) = write {
+                generator.write_done(&write);
+                writes += 1;
+            }
+            reads += read.is_some() as u32;
+        }
+        assert_eq!(writes + reads, 1000);
+        assert!(reads > 400 && writes > 400);
+
+        let mut ctx = TrafficContext {
+            layout: &layout,
+            rng: &mut rng,
+            tick: 1000,
+            outstanding: 4,
+            in_flight: &in_flight,
+        };
+        assert!(generator.next_write(&mut ctx).is_none());
+    }
+}